@@ -1,5 +1,5 @@
 #![doc = "Binary for building schema definitions from odra contracts."]
-#[allow(unused_imports)]
+#[allow(unused_imports, clippy::single_component_path_imports)]
 use yield_optimizer;
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -1,7 +1,8 @@
 use yield_optimizer::YieldOptimizer;
 
 fn main() {
-    odra_cli::Cli::default()
-        .with_contract::<YieldOptimizer>()
+    odra_cli::OdraCli::new()
+        .contract::<YieldOptimizer>()
+        .build()
         .run();
 }
@@ -1,12 +1,46 @@
-use super::*;
+use crate::yield_optimizer::{
+    ApyUpdate, Deposit, EmergencyExit, ManagementFeeUpdated, NewPool, Paused, PermitMessage,
+    PoolAdded, PoolAllocated, PoolAllocation, PoolApyUpdated, ReferralRecorded, RewardsHarvested,
+    Unpaused, Withdrawal, YieldOptimizer, YieldOptimizerHostRef, YieldOptimizerInitArgs,
+};
+use odra::casper_types::bytesrepr::{Bytes, ToBytes};
+use odra::casper_types::{runtime_args, RuntimeArgs, U256, U512};
 use odra::host::{Deployer, HostEnv, HostRef};
-use odra::casper_types::U512;
+use odra::prelude::*;
+use odra_modules::cep18_token::{Cep18, Cep18HostRef, Cep18InitArgs};
+
+fn setup() -> (HostEnv, YieldOptimizerHostRef) {
+    let env = odra_test::env();
+    let contract = YieldOptimizer::deploy(
+        &env,
+        YieldOptimizerInitArgs {
+            management_fee_bp: 100, // 1% management fee
+        },
+    );
+    (env, contract)
+}
+
+/// Serializes entry point arguments for a committee proposal
+fn call_args(args: RuntimeArgs) -> Bytes {
+    Bytes::from(args.to_bytes().unwrap())
+}
+
+/// Grows the deployer's 1-of-1 committee into a 2-of-3 committee of accounts 0, 1 and 2
+fn setup_committee() -> (HostEnv, YieldOptimizerHostRef) {
+    let (env, mut contract) = setup();
+
+    env.set_caller(env.get_account(0));
+    for i in 1..3 {
+        contract.propose(
+            "add_signer".to_string(),
+            call_args(runtime_args! { "signer" => env.get_account(i) }),
+        );
+    }
+    contract.propose(
+        "set_threshold".to_string(),
+        call_args(runtime_args! { "threshold" => 2u32 }),
+    );
 
-fn setup() -> (HostEnv, HostRef<YieldOptimizer>) {
-    let env = odra::host::env();
-    let mut contract = YieldOptimizerHostRef::deploy(&env, YieldOptimizerInitArgs {
-        management_fee_bp: 100, // 1% management fee
-    });
     (env, contract)
 }
 
@@ -16,7 +50,7 @@ fn test_initialization() {
 
     assert_eq!(contract.get_tvl(), U512::zero());
     assert_eq!(contract.get_total_shares(), U512::zero());
-    assert_eq!(contract.is_paused(), false);
+    assert!(!contract.is_paused().paused);
     assert_eq!(contract.get_management_fee(), 100);
 }
 
@@ -127,7 +161,7 @@ fn test_full_withdrawal() {
 }
 
 #[test]
-#[should_panic(expected = "Insufficient shares")]
+#[should_panic(expected = "InsufficientShares")]
 fn test_withdraw_insufficient_shares() {
     let (env, mut contract) = setup();
 
@@ -141,14 +175,1667 @@ fn test_withdraw_insufficient_shares() {
 }
 
 #[test]
-#[should_panic(expected = "Amount must be greater than zero")]
+#[should_panic(expected = "ZeroAmount")]
 fn test_deposit_zero_amount() {
     let (env, mut contract) = setup();
 
     let user = env.get_account(1);
     env.set_caller(user);
 
-    contract.deposit(U512::zero());
+    contract.deposit(U512::zero());
+}
+
+#[test]
+fn test_deposit_limits() {
+    let (env, mut contract) = setup();
+
+    let owner = env.get_account(0);
+    let user1 = env.get_account(1);
+    let user2 = env.get_account(2);
+
+    env.set_caller(owner);
+    contract.set_limits(
+        U512::from(5_000_000_000u64), // 5 CSPR TVL cap
+        U512::from(3_000_000_000u64), // 3 CSPR per user
+        U512::from(100_000_000u64),   // 0.1 CSPR minimum
+    );
+
+    let limits = contract.get_limits();
+    assert_eq!(limits.max_tvl, U512::from(5_000_000_000u64));
+    assert_eq!(limits.max_deposit_per_user, U512::from(3_000_000_000u64));
+    assert_eq!(limits.min_deposit, U512::from(100_000_000u64));
+
+    assert_eq!(contract.max_deposit(user1), U512::from(3_000_000_000u64));
+
+    env.set_caller(user1);
+    contract.deposit(U512::from(2_500_000_000u64));
+    assert_eq!(contract.max_deposit(user1), U512::from(500_000_000u64));
+
+    // The TVL cap binds before user 2's own cap
+    env.set_caller(user2);
+    contract.deposit(U512::from(2_000_000_000u64));
+    assert_eq!(contract.max_deposit(user2), U512::from(500_000_000u64));
+    assert_eq!(contract.max_deposit(user1), U512::from(500_000_000u64));
+}
+
+#[test]
+fn test_max_deposit_unlimited_and_paused() {
+    let (env, mut contract) = setup();
+
+    let user = env.get_account(1);
+    assert_eq!(contract.max_deposit(user), U512::MAX);
+
+    env.set_caller(env.get_account(0));
+    contract.pause();
+    assert_eq!(contract.max_deposit(user), U512::zero());
+}
+
+#[test]
+#[should_panic(expected = "TvlCapExceeded")]
+fn test_deposit_exceeds_tvl_cap() {
+    let (env, mut contract) = setup();
+
+    env.set_caller(env.get_account(0));
+    contract.set_limits(U512::from(1_000_000_000u64), U512::zero(), U512::zero());
+
+    env.set_caller(env.get_account(1));
+    contract.deposit(U512::from(2_000_000_000u64));
+}
+
+#[test]
+#[should_panic(expected = "UserDepositCapExceeded")]
+fn test_deposit_exceeds_user_cap() {
+    let (env, mut contract) = setup();
+
+    env.set_caller(env.get_account(0));
+    contract.set_limits(U512::zero(), U512::from(1_000_000_000u64), U512::zero());
+
+    env.set_caller(env.get_account(1));
+    contract.deposit(U512::from(600_000_000u64));
+    contract.deposit(U512::from(600_000_000u64));
+}
+
+#[test]
+#[should_panic(expected = "DepositBelowMinimum")]
+fn test_deposit_below_minimum() {
+    let (env, mut contract) = setup();
+
+    env.set_caller(env.get_account(0));
+    contract.set_limits(U512::zero(), U512::zero(), U512::from(100_000_000u64));
+
+    env.set_caller(env.get_account(1));
+    contract.deposit(U512::from(1_000u64));
+}
+
+#[test]
+fn test_allowlist_deposits() {
+    let (env, mut contract) = setup();
+
+    let owner = env.get_account(0);
+    let user1 = env.get_account(1);
+    let user2 = env.get_account(2);
+
+    env.set_caller(owner);
+    contract.set_allowlist_enabled(true);
+    contract.add_to_allowlist_batch(vec![user1, user2]);
+    assert!(contract.is_allowlist_enabled());
+    assert!(contract.is_allowlisted(user1));
+    assert!(contract.is_allowlisted(user2));
+    assert_eq!(contract.max_deposit(env.get_account(3)), U512::zero());
+
+    env.set_caller(user1);
+    contract.deposit(U512::from(1_000_000_000u64));
+    env.set_caller(user2);
+    contract.deposit(U512::from(2_000_000_000u64));
+
+    assert_eq!(contract.get_tvl(), U512::from(3_000_000_000u64));
+}
+
+#[test]
+#[should_panic(expected = "NotAllowlisted")]
+fn test_deposit_not_allowlisted() {
+    let (env, mut contract) = setup();
+
+    env.set_caller(env.get_account(0));
+    contract.set_allowlist_enabled(true);
+
+    env.set_caller(env.get_account(1));
+    contract.deposit(U512::from(1_000_000_000u64));
+}
+
+#[test]
+fn test_removed_from_allowlist_can_withdraw() {
+    let (env, mut contract) = setup();
+
+    let owner = env.get_account(0);
+    let user = env.get_account(1);
+
+    env.set_caller(owner);
+    contract.set_allowlist_enabled(true);
+    contract.add_to_allowlist(user);
+
+    env.set_caller(user);
+    contract.deposit(U512::from(1_000_000_000u64));
+
+    env.set_caller(owner);
+    contract.remove_from_allowlist(user);
+    assert!(!contract.is_allowlisted(user));
+    assert_eq!(contract.max_deposit(user), U512::zero());
+
+    env.set_caller(user);
+    contract.withdraw(U512::from(1_000_000_000u64));
+    assert_eq!(contract.get_position(user).shares, U512::zero());
+}
+
+/// Makes account 4 a guardian and lets account 1 deposit before being blocked
+fn setup_blocked_user() -> (HostEnv, YieldOptimizerHostRef) {
+    let (env, mut contract) = setup();
+
+    let guardian = env.get_account(4);
+    env.set_caller(env.get_account(0));
+    contract.set_guardian(guardian, true);
+
+    env.set_caller(env.get_account(1));
+    contract.deposit(U512::from(1_000_000_000u64));
+
+    env.set_caller(guardian);
+    contract.block_address(env.get_account(1));
+
+    (env, contract)
+}
+
+#[test]
+fn test_guardian_blocks_and_unblocks() {
+    let (env, mut contract) = setup_blocked_user();
+
+    let user = env.get_account(1);
+    assert!(contract.is_blocked(user));
+    assert!(env.emitted(&contract, "AddressBlocked"));
+
+    env.set_caller(env.get_account(4));
+    contract.unblock_address(user);
+    assert!(!contract.is_blocked(user));
+
+    env.set_caller(user);
+    contract.withdraw(U512::from(1_000_000_000u64));
+}
+
+#[test]
+#[should_panic(expected = "AddressBlocked")]
+fn test_blocked_address_cannot_withdraw() {
+    let (env, mut contract) = setup_blocked_user();
+
+    env.set_caller(env.get_account(1));
+    contract.withdraw(U512::from(1_000_000_000u64));
+}
+
+#[test]
+#[should_panic(expected = "AddressBlocked")]
+fn test_blocked_address_cannot_deposit() {
+    let (env, mut contract) = setup_blocked_user();
+
+    env.set_caller(env.get_account(1));
+    contract.deposit(U512::from(1_000_000_000u64));
+}
+
+#[test]
+fn test_blocked_address_has_no_max_deposit() {
+    let (env, contract) = setup_blocked_user();
+
+    assert_eq!(contract.max_deposit(env.get_account(1)), U512::zero());
+}
+
+#[test]
+#[should_panic(expected = "NotGuardian")]
+fn test_non_guardian_cannot_block() {
+    let (env, mut contract) = setup();
+
+    env.set_caller(env.get_account(2));
+    contract.block_address(env.get_account(1));
+}
+
+#[test]
+fn test_recover_blocked_position_after_timelock() {
+    let (env, mut contract) = setup_blocked_user();
+
+    let owner = env.get_account(0);
+    let lost = env.get_account(1);
+    let fresh = env.get_account(3);
+
+    env.set_caller(owner);
+    contract.queue_position_recovery(lost, fresh);
+    let pending = contract.get_pending_recovery(lost).unwrap();
+    assert_eq!(pending.to, fresh);
+
+    env.advance_block_time(2 * 24 * 60 * 60 * 1000);
+    contract.recover_blocked_position(lost, fresh);
+
+    // The position moves less two days of the 1% management fee
+    assert_eq!(contract.get_position(lost).shares, U512::zero());
+    assert_eq!(
+        contract.get_position(fresh).shares,
+        U512::from(999_945_206u64)
+    );
+    assert!(contract.get_pending_recovery(lost).is_none());
+
+    env.set_caller(fresh);
+    contract.withdraw(U512::from(999_945_206u64));
+}
+
+#[test]
+#[should_panic(expected = "RecoveryTimelocked")]
+fn test_recover_blocked_position_before_timelock() {
+    let (env, mut contract) = setup_blocked_user();
+
+    env.set_caller(env.get_account(0));
+    contract.queue_position_recovery(env.get_account(1), env.get_account(3));
+    contract.recover_blocked_position(env.get_account(1), env.get_account(3));
+}
+
+/// Deposits 10 CSPR from account 1 and allocates 8 CSPR, account 5 is a keeper
+fn setup_illiquid_vault() -> (HostEnv, YieldOptimizerHostRef) {
+    let (env, mut contract) = setup();
+
+    env.set_caller(env.get_account(1));
+    contract.deposit(U512::from(10_000_000_000u64));
+
+    env.set_caller(env.get_account(0));
+    contract.set_keeper(env.get_account(5), true);
+    contract.add_pool("Pool A".to_string(), 1250, 2);
+    contract.allocate_to_pool("Pool A".to_string(), U512::from(8_000_000_000u64));
+
+    (env, contract)
+}
+
+#[test]
+#[should_panic(expected = "InsufficientBalance")]
+fn test_withdraw_limited_to_idle_liquidity() {
+    let (env, mut contract) = setup_illiquid_vault();
+
+    env.set_caller(env.get_account(1));
+    contract.withdraw(U512::from(5_000_000_000u64));
+}
+
+#[test]
+fn test_withdrawal_queue_flow() {
+    let (env, mut contract) = setup_illiquid_vault();
+
+    let user = env.get_account(1);
+    let keeper = env.get_account(5);
+
+    env.set_caller(user);
+    let request_id = contract.request_withdrawal(U512::from(5_000_000_000u64));
+    assert_eq!(contract.get_withdrawal_queue_depth(), 1);
+    assert_eq!(
+        contract.get_position(user).shares,
+        U512::from(5_000_000_000u64)
+    );
+
+    // Not enough idle funds yet: the request stays queued
+    env.set_caller(keeper);
+    contract.process_withdrawals(10);
+    assert_eq!(contract.get_withdrawal_queue_depth(), 1);
+
+    contract.deallocate_from_pool("Pool A".to_string(), U512::from(3_000_000_000u64));
+    contract.process_withdrawals(10);
+    assert_eq!(contract.get_withdrawal_queue_depth(), 0);
+
+    let request = contract.get_withdrawal_request(request_id).unwrap();
+    assert!(request.processed);
+    assert_eq!(request.amount, U512::from(5_000_000_000u64));
+    assert_eq!(contract.get_tvl(), U512::from(5_000_000_000u64));
+    assert_eq!(
+        contract.get_reserved_for_withdrawals(),
+        U512::from(5_000_000_000u64)
+    );
+
+    env.set_caller(user);
+    assert_eq!(contract.get_pending_withdrawals(user).len(), 1);
+    contract.claim_withdrawal(request_id);
+    assert!(contract.get_pending_withdrawals(user).is_empty());
+    assert_eq!(contract.get_reserved_for_withdrawals(), U512::zero());
+}
+
+#[test]
+fn test_withdrawal_priced_at_processing() {
+    let (env, mut contract) = setup_illiquid_vault();
+
+    let user = env.get_account(1);
+    env.set_caller(user);
+    let request_id = contract.request_withdrawal(U512::from(1_000_000_000u64));
+
+    // Rewards harvested while the request waits raise its payout
+    env.set_caller(env.get_account(0));
+    contract.harvest_rewards("Pool A".to_string(), U512::from(1_000_000_000u64));
+
+    env.set_caller(env.get_account(5));
+    contract.process_withdrawals(1);
+
+    let request = contract.get_withdrawal_request(request_id).unwrap();
+    assert_eq!(request.amount, U512::from(1_100_000_000u64));
+}
+
+#[test]
+#[should_panic(expected = "WithdrawalNotProcessed")]
+fn test_claim_unprocessed_withdrawal() {
+    let (env, mut contract) = setup_illiquid_vault();
+
+    env.set_caller(env.get_account(1));
+    let request_id = contract.request_withdrawal(U512::from(5_000_000_000u64));
+    contract.claim_withdrawal(request_id);
+}
+
+#[test]
+#[should_panic(expected = "NotKeeper")]
+fn test_non_keeper_cannot_process_withdrawals() {
+    let (env, mut contract) = setup_illiquid_vault();
+
+    env.set_caller(env.get_account(1));
+    contract.process_withdrawals(10);
+}
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+const YEAR_MS: u64 = 365 * DAY_MS;
+const DEFAULT_VOTING_PERIOD: u64 = 3 * DAY_MS;
+
+/// One day cooldown with a one day window, account 1 holds 10 CSPR
+fn setup_cooldown() -> (HostEnv, YieldOptimizerHostRef) {
+    let (env, mut contract) = setup();
+    contract.set_cooldown_config(DAY_MS, DAY_MS);
+
+    env.set_caller(env.get_account(1));
+    contract.deposit(U512::from(10_000_000_000u64));
+
+    (env, contract)
+}
+
+#[test]
+fn test_withdraw_after_cooldown() {
+    let (env, mut contract) = setup_cooldown();
+    let user = env.get_account(1);
+
+    contract.start_cooldown(U512::from(4_000_000_000u64));
+    assert!(!contract.is_in_withdrawal_window(user));
+
+    env.advance_block_time(DAY_MS);
+    assert!(contract.is_in_withdrawal_window(user));
+    contract.withdraw(U512::from(3_000_000_000u64));
+    assert_eq!(
+        contract.get_cooldown(user).unwrap().shares,
+        U512::from(1_000_000_000u64)
+    );
+
+    contract.withdraw(U512::from(1_000_000_000u64));
+    assert!(contract.get_cooldown(user).is_none());
+}
+
+#[test]
+#[should_panic(expected = "CooldownNotStarted")]
+fn test_withdraw_requires_cooldown() {
+    let (_env, mut contract) = setup_cooldown();
+    contract.withdraw(U512::from(1_000_000_000u64));
+}
+
+#[test]
+#[should_panic(expected = "CooldownNotElapsed")]
+fn test_withdraw_during_cooldown() {
+    let (env, mut contract) = setup_cooldown();
+
+    contract.start_cooldown(U512::from(1_000_000_000u64));
+    env.advance_block_time(DAY_MS / 2);
+    contract.withdraw(U512::from(1_000_000_000u64));
+}
+
+#[test]
+#[should_panic(expected = "CooldownNotElapsed")]
+fn test_deposit_during_window_delays_cooldown() {
+    let (env, mut contract) = setup_cooldown();
+    let user = env.get_account(1);
+
+    contract.start_cooldown(U512::from(10_000_000_000u64));
+    env.advance_block_time(DAY_MS);
+
+    // Doubling the balance moves the cooldown start halfway to now
+    contract.deposit(U512::from(10_000_000_000u64));
+    let cooldown = contract.get_cooldown(user).unwrap();
+    assert!(cooldown.started_at > DAY_MS / 2 - 1000 && cooldown.started_at < DAY_MS);
+    assert_eq!(cooldown.withdrawable_from, cooldown.started_at + DAY_MS);
+    assert!(!contract.is_in_withdrawal_window(user));
+
+    contract.withdraw(U512::from(10_000_000_000u64));
+}
+
+#[test]
+#[should_panic(expected = "CooldownWindowClosed")]
+fn test_withdraw_after_window_closed() {
+    let (env, mut contract) = setup_cooldown();
+
+    contract.start_cooldown(U512::from(1_000_000_000u64));
+    env.advance_block_time(2 * DAY_MS + 1);
+    contract.withdraw(U512::from(1_000_000_000u64));
+}
+
+#[test]
+fn test_emergency_mode_bypasses_cooldown() {
+    let (env, mut contract) = setup_cooldown();
+
+    env.set_caller(env.get_account(0));
+    contract.enter_emergency_mode("incident".to_string());
+
+    env.set_caller(env.get_account(1));
+    contract.withdraw(U512::from(10_000_000_000u64));
+    assert_eq!(contract.get_tvl(), U512::zero());
+}
+
+/// Delegation pool on validator 0, account 1 deposits and funds 1000 CSPR, account 5 is a keeper
+fn setup_delegation() -> (HostEnv, YieldOptimizerHostRef) {
+    let (env, mut contract) = setup();
+    let amount = U512::from(1_000_000_000_000u64);
+
+    contract.set_keeper(env.get_account(5), true);
+    contract.set_unbonding_period(env.unbonding_delay());
+    contract.add_delegation_pool("Validator 0".to_string(), env.get_validator(0), 1);
+
+    env.set_caller(env.get_account(1));
+    contract.deposit(amount);
+    contract.with_tokens(amount).fund_vault();
+
+    env.set_caller(env.get_account(0));
+    contract.allocate_to_pool("Validator 0".to_string(), amount);
+
+    (env, contract)
+}
+
+#[test]
+#[should_panic(expected = "NotDelegationPool")]
+fn test_rebalance_out_of_delegation_pool() {
+    let (_env, mut contract) = setup_delegation();
+
+    contract.add_pool("Pool A".to_string(), 1000, 1);
+    contract.rebalance_pools(
+        "Validator 0".to_string(),
+        "Pool A".to_string(),
+        U512::from(1_000_000_000u64),
+    );
+}
+
+#[test]
+fn test_delegation_pool_delegates() {
+    let (env, contract) = setup_delegation();
+    let amount = U512::from(1_000_000_000_000u64);
+
+    assert_eq!(
+        env.delegated_amount(contract.address(), env.get_validator(0)),
+        amount
+    );
+    assert_eq!(
+        contract.get_delegated_amount("Validator 0".to_string()),
+        amount
+    );
+    assert_eq!(env.balance_of(&contract), U512::zero());
+}
+
+#[test]
+fn test_delegation_rewards_synced_from_auction() {
+    let (env, mut contract) = setup_delegation();
+    let amount = U512::from(1_000_000_000_000u64);
+
+    env.advance_with_auctions(env.auction_delay() * 2);
+
+    env.set_caller(env.get_account(5));
+    contract.sync_delegation("Validator 0".to_string());
+
+    let delegated = contract.get_delegated_amount("Validator 0".to_string());
+    assert!(delegated > amount);
+    assert_eq!(contract.get_tvl(), delegated);
+    let pool = contract.get_pool_info("Validator 0".to_string()).unwrap();
+    assert_eq!(pool.total_allocated, delegated);
+
+    // The sync annualizes the rewards earned since the pool was added
+    let year_ms = U512::from(365u64 * 24 * 60 * 60 * 1000);
+    let apy = (delegated - amount) * U512::from(10000u32) * year_ms
+        / (amount * U512::from(env.block_time()));
+    assert!(pool.current_apy > 0);
+    assert_eq!(U512::from(pool.current_apy), apy);
+    let history = contract.get_pool_apy_history("Validator 0".to_string(), 0, 10);
+    assert_eq!(history.last().unwrap().apy, pool.current_apy);
+}
+
+#[test]
+fn test_emergency_exit_books_unsynced_delegation_rewards() {
+    let (env, mut contract) = setup_delegation();
+    let amount = U512::from(1_000_000_000_000u64);
+
+    env.advance_with_auctions(env.auction_delay() * 2);
+    let delegated = contract.get_delegated_amount("Validator 0".to_string());
+    assert!(delegated > amount);
+
+    contract.emergency_exit();
+
+    assert_eq!(contract.get_tvl(), delegated);
+    assert_eq!(contract.get_total_unbonding(), delegated);
+    let event: EmergencyExit = env.get_event(&contract, -1).unwrap();
+    assert_eq!(event.total_recovered, delegated);
+    assert_eq!(event.loss, U512::zero());
+}
+
+#[test]
+fn test_delegation_pool_undelegates() {
+    let (env, mut contract) = setup_delegation();
+
+    env.set_caller(env.get_account(5));
+    contract.deallocate_from_pool("Validator 0".to_string(), U512::from(400_000_000_000u64));
+    assert_eq!(
+        contract.get_delegated_amount("Validator 0".to_string()),
+        U512::from(600_000_000_000u64)
+    );
+
+    // Motes return to the vault purse after the unbonding delay
+    env.advance_with_auctions(env.unbonding_delay() + env.auction_delay());
+    assert!(env.balance_of(&contract) >= U512::from(400_000_000_000u64));
+}
+
+#[test]
+fn test_unbonding_tracked_until_claimed() {
+    let (env, mut contract) = setup_delegation();
+    let unbonding = U512::from(400_000_000_000u64);
+
+    env.set_caller(env.get_account(5));
+    contract.deallocate_from_pool("Validator 0".to_string(), unbonding);
+
+    // Funds in transit are neither idle nor dropped from the books
+    assert_eq!(contract.get_total_unbonding(), unbonding);
+    assert_eq!(contract.get_idle_liquidity(), U512::zero());
+    assert_eq!(contract.total_assets(), contract.get_tvl());
+    assert_eq!(contract.get_unbonding(env.get_validator(0)).len(), 1);
+
+    // Nothing matured yet
+    assert_eq!(contract.claim_unbonded(), U512::zero());
+
+    env.advance_block_time(env.unbonding_delay());
+    assert_eq!(contract.claim_unbonded(), unbonding);
+    assert_eq!(contract.get_total_unbonding(), U512::zero());
+    assert_eq!(contract.get_idle_liquidity(), unbonding);
+    assert!(contract.get_unbonding(env.get_validator(0)).is_empty());
+}
+
+#[test]
+#[should_panic(expected = "DelegationRewardsNotDeclarable")]
+fn test_cannot_declare_delegation_rewards() {
+    let (_env, mut contract) = setup_delegation();
+    contract.harvest_rewards("Validator 0".to_string(), U512::from(1_000_000_000u64));
+}
+
+/// Three delegation pools weighted 50/30/20, account 1 deposits and funds 1000 CSPR
+fn setup_strategy() -> (HostEnv, YieldOptimizerHostRef) {
+    let (env, mut contract) = setup();
+    let amount = U512::from(1_000_000_000_000u64);
+
+    contract.set_keeper(env.get_account(5), true);
+    contract.set_unbonding_period(env.unbonding_delay());
+    for (i, weight) in [5000u32, 3000, 2000].into_iter().enumerate() {
+        let name = format!("Validator {}", i);
+        contract.add_delegation_pool(name.clone(), env.get_validator(i), 1);
+        contract.set_validator_target(name, weight, U512::zero(), U512::zero());
+    }
+
+    env.set_caller(env.get_account(1));
+    contract.deposit(amount);
+    contract.with_tokens(amount).fund_vault();
+
+    (env, contract)
+}
+
+#[test]
+fn test_removing_validator_target_unbonds_stake() {
+    let (env, mut contract) = setup_strategy();
+
+    env.set_caller(env.get_account(5));
+    contract.rebalance_delegations();
+    let stake = contract
+        .get_pool_info("Validator 2".to_string())
+        .unwrap()
+        .total_allocated;
+
+    env.set_caller(env.get_account(0));
+    contract.set_validator_target("Validator 2".to_string(), 0, U512::zero(), U512::zero());
+
+    assert_eq!(
+        contract
+            .get_pool_info("Validator 2".to_string())
+            .unwrap()
+            .total_allocated,
+        U512::zero()
+    );
+    assert_eq!(
+        contract.get_unbonding(env.get_validator(2))[0].amount,
+        stake
+    );
+    assert_eq!(contract.get_total_unbonding(), stake);
+}
+
+#[test]
+fn test_sync_refreshes_delegation_pool_apy() {
+    let (env, mut contract) = setup_strategy();
+
+    env.set_caller(env.get_account(0));
+    contract.set_apy_guards(0, 60_000);
+    env.advance_block_time(60_001);
+    assert_eq!(contract.get_stale_pools().len(), 3);
+
+    env.set_caller(env.get_account(5));
+    for i in 0..3 {
+        contract.sync_delegation(format!("Validator {}", i));
+    }
+    assert!(contract.get_stale_pools().is_empty());
+    contract.rebalance_delegations();
+}
+
+#[test]
+fn test_rebalance_delegations_by_weight() {
+    let (env, mut contract) = setup_strategy();
+
+    env.set_caller(env.get_account(5));
+    contract.rebalance_delegations();
+
+    let weights = contract.get_validator_weights();
+    assert_eq!(weights.len(), 3);
+    for weight in weights {
+        assert_eq!(weight.actual_weight, weight.target_weight);
+    }
+    assert_eq!(
+        contract.get_delegated_amount("Validator 1".to_string()),
+        U512::from(300_000_000_000u64)
+    );
+}
+
+#[test]
+fn test_rebalance_respects_max_delegation() {
+    let (env, mut contract) = setup_strategy();
+
+    env.set_caller(env.get_account(0));
+    contract.set_validator_target(
+        "Validator 0".to_string(),
+        5000,
+        U512::zero(),
+        U512::from(100_000_000_000u64),
+    );
+
+    env.set_caller(env.get_account(5));
+    contract.rebalance_delegations();
+
+    assert_eq!(
+        contract.get_delegated_amount("Validator 0".to_string()),
+        U512::from(100_000_000_000u64)
+    );
+    assert_eq!(
+        contract.get_idle_liquidity(),
+        U512::from(400_000_000_000u64)
+    );
+}
+
+#[test]
+fn test_rebalance_redelegates_removed_validator() {
+    let (env, mut contract) = setup_strategy();
+
+    env.set_caller(env.get_account(5));
+    contract.rebalance_delegations();
+
+    env.remove_validator(0);
+    contract.rebalance_delegations();
+    assert_eq!(
+        contract.get_total_unbonding(),
+        U512::from(500_000_000_000u64)
+    );
+    assert_eq!(contract.get_strategy_pools().len(), 2);
+
+    // Once the stake is back it is spread over the remaining validators
+    env.advance_with_auctions(env.unbonding_delay());
+    contract.claim_unbonded();
+    contract.rebalance_delegations();
+    assert_eq!(contract.get_idle_liquidity(), U512::zero());
+
+    let weights = contract.get_validator_weights();
+    assert_eq!(weights[0].target_weight, 6000);
+    assert_eq!(weights[0].actual_weight, 6000);
+    assert_eq!(weights[1].target_weight, 4000);
+    assert_eq!(weights[1].actual_weight, 4000);
+}
+
+#[test]
+#[should_panic(expected = "InvalidValidatorTarget")]
+fn test_validator_weights_cannot_exceed_total() {
+    let (env, mut contract) = setup_strategy();
+
+    env.set_caller(env.get_account(0));
+    contract.add_delegation_pool("Validator 3".to_string(), env.get_validator(3), 1);
+    contract.set_validator_target("Validator 3".to_string(), 1, U512::zero(), U512::zero());
+}
+
+#[test]
+fn test_pool_admin_events() {
+    let (env, mut contract) = setup();
+
+    contract.add_pool("Pool A".to_string(), 1250, 2);
+    assert!(env.emitted_event(
+        &contract,
+        PoolAdded {
+            pool: "Pool A".to_string(),
+            initial_apy: 1250,
+            risk_level: 2,
+            timestamp: env.block_time(),
+            nonce: contract.get_event_nonce() - 1,
+        }
+    ));
+
+    contract.update_pool_apy("Pool A".to_string(), 1500);
+    assert!(env.emitted_event(
+        &contract,
+        PoolApyUpdated {
+            pool: "Pool A".to_string(),
+            old_apy: 1250,
+            new_apy: 1500,
+            timestamp: env.block_time(),
+            nonce: contract.get_event_nonce() - 1,
+        }
+    ));
+
+    contract.deposit(U512::from(1_000_000_000u64));
+    contract.allocate_to_pool("Pool A".to_string(), U512::from(500_000_000u64));
+    assert!(env.emitted_event(
+        &contract,
+        PoolAllocated {
+            pool: "Pool A".to_string(),
+            amount: U512::from(500_000_000u64),
+            timestamp: env.block_time(),
+            nonce: contract.get_event_nonce() - 1,
+        }
+    ));
+}
+
+#[test]
+fn test_pause_events() {
+    let (env, mut contract) = setup();
+
+    contract.pause();
+    assert!(env.emitted_event(
+        &contract,
+        Paused {
+            reason: String::new(),
+            timestamp: env.block_time(),
+            nonce: contract.get_event_nonce() - 1,
+        }
+    ));
+
+    contract.unpause();
+    assert!(env.emitted_event(
+        &contract,
+        Unpaused {
+            timestamp: env.block_time(),
+            nonce: contract.get_event_nonce() - 1,
+        }
+    ));
+}
+
+#[test]
+fn test_set_management_fee() {
+    let (env, mut contract) = setup();
+
+    contract.set_management_fee(300);
+    assert_eq!(contract.get_management_fee(), 300);
+    assert!(env.emitted_event(
+        &contract,
+        ManagementFeeUpdated {
+            old_fee: 100,
+            new_fee: 300,
+            timestamp: env.block_time(),
+            nonce: contract.get_event_nonce() - 1,
+        }
+    ));
+}
+
+#[test]
+fn test_event_nonces_are_sequential() {
+    let (env, mut contract) = setup();
+
+    contract.deposit(U512::from(1_000_000_000u64));
+    contract.add_pool("Pool A".to_string(), 1250, 2);
+    contract.withdraw(U512::from(500_000_000u64));
+
+    let first: Deposit = env.get_event(&contract, 0).unwrap();
+    let last: Withdrawal = env.get_event(&contract, -1).unwrap();
+    assert_eq!(first.nonce, 0);
+    assert_eq!(last.nonce, 2);
+    assert_eq!(contract.get_event_nonce(), 3);
+}
+
+#[test]
+fn test_events_carry_post_state() {
+    let (env, mut contract) = setup();
+    let user = env.get_account(0);
+
+    contract.deposit(U512::from(1_000_000_000u64));
+    let deposit: Deposit = env.get_event(&contract, -1).unwrap();
+    assert_eq!(deposit.user_shares, contract.get_position(user).shares);
+    assert_eq!(deposit.total_tvl, U512::from(1_000_000_000u64));
+    assert_eq!(deposit.total_shares, U512::from(1_000_000_000u64));
+
+    contract.add_pool("Pool A".to_string(), 1250, 2);
+    contract.harvest_rewards("Pool A".to_string(), U512::from(500_000_000u64));
+    let harvest: RewardsHarvested = env.get_event(&contract, -1).unwrap();
+    assert_eq!(harvest.price_per_share, U512::from(1_500_000_000u64));
+
+    contract.withdraw(U512::from(400_000_000u64));
+    let withdrawal: Withdrawal = env.get_event(&contract, -1).unwrap();
+    assert_eq!(withdrawal.amount, U512::from(600_000_000u64));
+    assert_eq!(withdrawal.user_shares, U512::from(600_000_000u64));
+    assert_eq!(withdrawal.total_tvl, U512::from(900_000_000u64));
+    assert_eq!(withdrawal.total_shares, U512::from(600_000_000u64));
+}
+
+#[test]
+fn test_pps_checkpoints_on_harvest() {
+    let (env, mut contract) = setup();
+
+    contract.deposit(U512::from(10_000_000_000u64));
+    contract.add_pool("Pool A".to_string(), 1250, 2);
+    contract.harvest_rewards("Pool A".to_string(), U512::from(1_000_000_000u64));
+
+    let history = contract.get_pps_history(10);
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].price_per_share, U512::from(1_000_000_000u64));
+    assert_eq!(history[1].price_per_share, U512::from(1_100_000_000u64));
+    assert_eq!(contract.get_pps_history(1)[0], history[1]);
+
+    // Harvests within the interval don't add checkpoints
+    contract.set_pps_checkpoint_interval(60_000);
+    contract.harvest_rewards("Pool A".to_string(), U512::from(1_000_000_000u64));
+    assert_eq!(contract.get_pps_history(10).len(), 2);
+
+    env.advance_block_time(60_000);
+    contract.harvest_rewards("Pool A".to_string(), U512::from(1_000_000_000u64));
+    assert_eq!(contract.get_pps_history(10).len(), 3);
+}
+
+#[test]
+fn test_realized_apy() {
+    let (env, mut contract) = setup();
+    let year = 365 * 24 * 60 * 60;
+
+    contract.deposit(U512::from(10_000_000_000u64));
+    contract.add_pool("Pool A".to_string(), 1250, 2);
+
+    // 5% over half a year annualizes to 10%
+    env.advance_block_time(year * 1000 / 2);
+    contract.harvest_rewards("Pool A".to_string(), U512::from(500_000_000u64));
+    assert_eq!(contract.get_realized_apy(year), 1000);
+
+    // No checkpoint inside a window that is too short
+    env.advance_block_time(10_000);
+    assert_eq!(contract.get_realized_apy(1), 0);
+}
+
+#[test]
+fn test_oracle_updates_apy() {
+    let (env, mut contract) = setup();
+    let oracle = env.get_account(6);
+
+    contract.add_pool("Pool A".to_string(), 1250, 2);
+    contract.set_oracle(oracle, true);
+    env.advance_block_time(1000);
+
+    env.set_caller(oracle);
+    contract.update_pool_apy("Pool A".to_string(), 1300);
+
+    let pool = contract.get_pool_info("Pool A".to_string()).unwrap();
+    assert_eq!(pool.current_apy, 1300);
+    assert_eq!(pool.apy_updated_at, env.block_time());
+}
+
+#[test]
+#[should_panic(expected = "ApyChangeTooLarge")]
+fn test_apy_change_limited() {
+    let (_env, mut contract) = setup();
+
+    contract.add_pool("Pool A".to_string(), 1250, 2);
+    contract.set_apy_guards(100, 0);
+    contract.update_pool_apy("Pool A".to_string(), 1400);
+}
+
+#[test]
+fn test_stale_pools() {
+    let (env, mut contract) = setup();
+
+    contract.add_pool("Pool A".to_string(), 1250, 2);
+    contract.add_pool("Pool B".to_string(), 800, 1);
+    contract.set_apy_guards(0, 60_000);
+
+    env.advance_block_time(60_001);
+    contract.update_pool_apy("Pool B".to_string(), 850);
+    assert_eq!(contract.get_stale_pools(), vec!["Pool A".to_string()]);
+}
+
+#[test]
+#[should_panic(expected = "StaleApy")]
+fn test_rebalance_delegations_refuses_stale_apy() {
+    let (env, mut contract) = setup_strategy();
+
+    env.set_caller(env.get_account(0));
+    contract.set_apy_guards(0, 60_000);
+    env.advance_block_time(60_001);
+
+    env.set_caller(env.get_account(5));
+    contract.rebalance_delegations();
+}
+
+#[test]
+fn test_pool_apy_history() {
+    let (env, mut contract) = setup();
+
+    contract.add_pool("Pool A".to_string(), 1000, 2);
+    for apy in [1100, 1200, 1300] {
+        env.advance_block_time(1000);
+        contract.update_pool_apy("Pool A".to_string(), apy);
+    }
+
+    let history = contract.get_pool_apy_history("Pool A".to_string(), 0, 10);
+    assert_eq!(history.len(), 4);
+    assert_eq!(history[0].apy, 1000);
+    assert_eq!(history[3].apy, 1300);
+
+    let page = contract.get_pool_apy_history("Pool A".to_string(), 1, 2);
+    assert_eq!(page.len(), 2);
+    assert_eq!(page[0].apy, 1100);
+    assert_eq!(page[1].apy, 1200);
+}
+
+#[test]
+fn test_time_weighted_apy() {
+    let (env, mut contract) = setup();
+
+    contract.add_pool("Pool A".to_string(), 1000, 2);
+    env.advance_block_time(30_000);
+    contract.update_pool_apy("Pool A".to_string(), 2000);
+    env.advance_block_time(10_000);
+
+    // 1000 for 30s and 2000 for 10s
+    assert_eq!(
+        contract.get_time_weighted_apy("Pool A".to_string(), 40),
+        1250
+    );
+    // 1000 for 10s and 2000 for 10s
+    assert_eq!(
+        contract.get_time_weighted_apy("Pool A".to_string(), 20),
+        1500
+    );
+}
+
+#[test]
+fn test_deposit_for() {
+    let (env, mut contract) = setup();
+    let router = env.get_account(0);
+    let beneficiary = env.get_account(1);
+
+    contract.deposit_for(beneficiary, U512::from(1_000_000_000u64));
+
+    assert_eq!(contract.get_position(router).shares, U512::zero());
+    assert_eq!(
+        contract.get_position(beneficiary).shares,
+        U512::from(1_000_000_000u64)
+    );
+
+    let event: Deposit = env.get_event(&contract, -1).unwrap();
+    assert_eq!(event.caller, router);
+    assert_eq!(event.owner, beneficiary);
+    assert_eq!(event.receiver, beneficiary);
+    assert_eq!(event.user, beneficiary);
+}
+
+#[test]
+fn test_withdraw_to() {
+    let (env, mut contract) = setup();
+    let user = env.get_account(0);
+    let recipient = env.get_account(2);
+
+    contract.deposit(U512::from(1_000_000_000u64));
+    contract.withdraw_to(U512::from(400_000_000u64), recipient);
+
+    assert_eq!(
+        contract.get_position(user).shares,
+        U512::from(600_000_000u64)
+    );
+    let event: Withdrawal = env.get_event(&contract, -1).unwrap();
+    assert_eq!(event.caller, user);
+    assert_eq!(event.owner, user);
+    assert_eq!(event.receiver, recipient);
+    assert_eq!(event.user, user);
+    assert_eq!(event.amount, U512::from(400_000_000u64));
+}
+
+#[test]
+#[should_panic(expected = "NotAllowlisted")]
+fn test_deposit_for_checks_beneficiary_allowlist() {
+    let (env, mut contract) = setup();
+
+    contract.set_allowlist_enabled(true);
+    contract.add_to_allowlist(env.get_account(0));
+    contract.deposit_for(env.get_account(1), U512::from(1_000_000_000u64));
+}
+
+#[test]
+#[should_panic(expected = "AddressBlocked")]
+fn test_withdraw_to_blocked_recipient() {
+    let (env, mut contract) = setup_blocked_user();
+
+    env.set_caller(env.get_account(0));
+    contract.deposit(U512::from(1_000_000_000u64));
+    contract.withdraw_to(U512::from(1_000_000_000u64), env.get_account(1));
+}
+
+#[test]
+fn test_operator_withdraws_for_owner() {
+    let (env, mut contract) = setup();
+    let owner = env.get_account(0);
+    let operator = env.get_account(3);
+    let recipient = env.get_account(4);
+
+    contract.deposit(U512::from(1_000_000_000u64));
+    contract.set_operator(operator, true);
+    assert!(contract.is_operator(owner, operator));
+
+    env.set_caller(operator);
+    contract.withdraw_from(owner, U512::from(250_000_000u64), recipient);
+
+    assert_eq!(
+        contract.get_position(owner).shares,
+        U512::from(750_000_000u64)
+    );
+    let event: Withdrawal = env.get_event(&contract, -1).unwrap();
+    assert_eq!(event.caller, operator);
+    assert_eq!(event.owner, owner);
+    assert_eq!(event.receiver, recipient);
+}
+
+#[test]
+#[should_panic(expected = "NotOperator")]
+fn test_unapproved_operator_cannot_withdraw() {
+    let (env, mut contract) = setup();
+    let owner = env.get_account(0);
+
+    contract.deposit(U512::from(1_000_000_000u64));
+
+    env.set_caller(env.get_account(3));
+    contract.withdraw_from(owner, U512::from(1_000_000_000u64), env.get_account(3));
+}
+
+#[test]
+#[should_panic(expected = "NotOperator")]
+fn test_revoked_operator_cannot_withdraw() {
+    let (env, mut contract) = setup();
+    let owner = env.get_account(0);
+    let operator = env.get_account(3);
+
+    contract.deposit(U512::from(1_000_000_000u64));
+    contract.set_operator(operator, true);
+    contract.set_operator(operator, false);
+    assert!(!contract.is_operator(owner, operator));
+
+    env.set_caller(operator);
+    contract.withdraw_from(owner, U512::from(1_000_000_000u64), operator);
+}
+
+/// Sign a permit for `owner` with the message the contract rebuilds
+fn sign_permit(
+    env: &HostEnv,
+    contract: &YieldOptimizerHostRef,
+    action: &str,
+    owner: Address,
+    target: Address,
+    amount: U512,
+    deadline: u64,
+) -> Bytes {
+    let message = PermitMessage {
+        action: action.to_string(),
+        owner,
+        target,
+        amount,
+        nonce: contract.get_permit_nonce(owner),
+        deadline,
+        contract: contract.address(),
+    };
+    env.sign_message(&Bytes::from(message.to_bytes().unwrap()), &owner)
+}
+
+#[test]
+fn test_withdraw_with_permit() {
+    let (env, mut contract) = setup();
+    let owner = env.get_account(1);
+    let relayer = env.get_account(7);
+    let shares = U512::from(400_000_000u64);
+
+    env.set_caller(owner);
+    contract.deposit(U512::from(1_000_000_000u64));
+
+    let deadline = env.block_time() + 60_000;
+    let signature = sign_permit(&env, &contract, "withdraw", owner, owner, shares, deadline);
+
+    env.set_caller(relayer);
+    contract.withdraw_with_permit(env.public_key(&owner), shares, owner, deadline, signature);
+
+    assert_eq!(
+        contract.get_position(owner).shares,
+        U512::from(600_000_000u64)
+    );
+    assert_eq!(contract.get_permit_nonce(owner), 1);
+}
+
+#[test]
+fn test_set_operator_with_permit() {
+    let (env, mut contract) = setup();
+    let owner = env.get_account(1);
+    let operator = env.get_account(3);
+
+    let deadline = env.block_time() + 60_000;
+    let signature = sign_permit(
+        &env,
+        &contract,
+        "set_operator",
+        owner,
+        operator,
+        U512::one(),
+        deadline,
+    );
+
+    env.set_caller(env.get_account(7));
+    contract.set_operator_with_permit(env.public_key(&owner), operator, true, deadline, signature);
+    assert!(contract.is_operator(owner, operator));
+}
+
+#[test]
+#[should_panic(expected = "InvalidSignature")]
+fn test_permit_cannot_be_replayed() {
+    let (env, mut contract) = setup();
+    let owner = env.get_account(1);
+    let shares = U512::from(100_000_000u64);
+
+    env.set_caller(owner);
+    contract.deposit(U512::from(1_000_000_000u64));
+
+    let deadline = env.block_time() + 60_000;
+    let signature = sign_permit(&env, &contract, "withdraw", owner, owner, shares, deadline);
+
+    env.set_caller(env.get_account(7));
+    contract.withdraw_with_permit(
+        env.public_key(&owner),
+        shares,
+        owner,
+        deadline,
+        signature.clone(),
+    );
+    contract.withdraw_with_permit(env.public_key(&owner), shares, owner, deadline, signature);
+}
+
+#[test]
+#[should_panic(expected = "InvalidSignature")]
+fn test_permit_signed_by_other_key() {
+    let (env, mut contract) = setup();
+    let owner = env.get_account(1);
+    let attacker = env.get_account(7);
+    let shares = U512::from(100_000_000u64);
+
+    env.set_caller(owner);
+    contract.deposit(U512::from(1_000_000_000u64));
+
+    // The attacker signs a message naming the owner, with their own key
+    let deadline = env.block_time() + 60_000;
+    let message = PermitMessage {
+        action: "withdraw".to_string(),
+        owner,
+        target: attacker,
+        amount: shares,
+        nonce: 0,
+        deadline,
+        contract: contract.address(),
+    };
+    let signature = env.sign_message(&Bytes::from(message.to_bytes().unwrap()), &attacker);
+
+    env.set_caller(attacker);
+    contract.withdraw_with_permit(
+        env.public_key(&owner),
+        shares,
+        attacker,
+        deadline,
+        signature,
+    );
+}
+
+#[test]
+#[should_panic(expected = "PermitExpired")]
+fn test_expired_permit() {
+    let (env, mut contract) = setup();
+    let owner = env.get_account(1);
+    let shares = U512::from(100_000_000u64);
+
+    env.set_caller(owner);
+    contract.deposit(U512::from(1_000_000_000u64));
+
+    let deadline = env.block_time() + 60_000;
+    let signature = sign_permit(&env, &contract, "withdraw", owner, owner, shares, deadline);
+
+    env.advance_block_time(60_001);
+    contract.withdraw_with_permit(env.public_key(&owner), shares, owner, deadline, signature);
+}
+
+fn new_pool(name: &str, initial_apy: u32) -> NewPool {
+    NewPool {
+        name: name.to_string(),
+        initial_apy,
+        risk_level: 2,
+    }
+}
+
+#[test]
+fn test_batch_vault_setup() {
+    let (_env, mut contract) = setup();
+
+    contract.deposit(U512::from(10_000_000_000u64));
+    contract.add_pools(vec![new_pool("Pool A", 1000), new_pool("Pool B", 800)]);
+    contract.update_apys(vec![
+        ApyUpdate {
+            pool: "Pool A".to_string(),
+            apy: 1100,
+        },
+        ApyUpdate {
+            pool: "Pool B".to_string(),
+            apy: 900,
+        },
+    ]);
+    contract.allocate_many(vec![
+        PoolAllocation {
+            pool: "Pool A".to_string(),
+            amount: U512::from(3_000_000_000u64),
+        },
+        PoolAllocation {
+            pool: "Pool B".to_string(),
+            amount: U512::from(2_000_000_000u64),
+        },
+    ]);
+
+    let pool_a = contract.get_pool_info("Pool A".to_string()).unwrap();
+    let pool_b = contract.get_pool_info("Pool B".to_string()).unwrap();
+    assert_eq!(pool_a.current_apy, 1100);
+    assert_eq!(pool_b.current_apy, 900);
+    assert_eq!(pool_a.total_allocated, U512::from(3_000_000_000u64));
+    assert_eq!(pool_b.total_allocated, U512::from(2_000_000_000u64));
+    assert_eq!(contract.get_total_allocated(), U512::from(5_000_000_000u64));
+}
+
+#[test]
+fn test_batch_reverts_atomically() {
+    let (_env, mut contract) = setup();
+
+    // The duplicate pool makes the whole batch fail
+    let result = contract.try_add_pools(vec![
+        new_pool("Pool A", 1000),
+        new_pool("Pool B", 800),
+        new_pool("Pool A", 1200),
+    ]);
+    assert!(result.is_err());
+    assert!(contract.get_pool_names().is_empty());
+    assert!(contract.get_pool_info("Pool B".to_string()).is_none());
+}
+
+#[test]
+fn test_referral_recorded_on_first_deposit() {
+    let (env, mut contract) = setup();
+    let user = env.get_account(1);
+    let referrer = env.get_account(2);
+
+    env.set_caller(user);
+    contract.deposit_with_referrer(U512::from(1_000_000_000u64), referrer);
+    assert_eq!(contract.get_referrer(user), Some(referrer));
+    assert!(env.emitted(&contract, "ReferralRecorded"));
+    let event: ReferralRecorded = env.get_event(&contract, -2).unwrap();
+    assert_eq!(event.referrer, referrer);
+
+    // Later referrers are ignored
+    contract.deposit_with_referrer(U512::from(1_000_000_000u64), env.get_account(3));
+    assert_eq!(contract.get_referrer(user), Some(referrer));
+
+    let stats = contract.get_referral_stats(referrer);
+    assert_eq!(stats.referred_users, 1);
+    assert_eq!(stats.referred_deposits, U512::from(2_000_000_000u64));
+}
+
+#[test]
+fn test_referrer_earns_fee_share() {
+    let (env, mut contract) = setup();
+    let (user, referrer, other) = (env.get_account(1), env.get_account(2), env.get_account(3));
+
+    // 1% management fee, half of it to referrers
+    contract.set_referral_share(5000);
+
+    env.set_caller(user);
+    contract.deposit_with_referrer(U512::from(100_000_000_000u64), referrer);
+    env.set_caller(other);
+    contract.deposit(U512::from(100_000_000_000u64));
+
+    env.advance_block_time(365 * 24 * 60 * 60 * 1000);
+    env.set_caller(user);
+    contract.withdraw(U512::from(1_000_000_000u64));
+    env.set_caller(other);
+    contract.withdraw(U512::from(1_000_000_000u64));
+
+    // A year at 1% costs 1 of 100 shares with or without a referrer,
+    // half of the referred user's fee goes to the referrer
+    assert_eq!(
+        contract.get_position(user).shares,
+        U512::from(98_000_000_000u64)
+    );
+    assert_eq!(
+        contract.get_position(other).shares,
+        U512::from(98_000_000_000u64)
+    );
+    assert_eq!(
+        contract.get_position(referrer).shares,
+        U512::from(500_000_000u64)
+    );
+    assert_eq!(contract.get_total_shares(), U512::from(196_500_000_000u64));
+    assert_eq!(
+        contract.get_referral_stats(referrer).earnings,
+        U512::from(500_000_000u64)
+    );
+}
+
+#[test]
+fn test_management_fee_is_capped_at_the_position() {
+    let (env, mut contract) = setup();
+    let (user, referrer) = (env.get_account(1), env.get_account(2));
+    contract.set_management_fee(10000);
+    contract.set_referral_share(5000);
+
+    env.set_caller(user);
+    contract.deposit_with_referrer(U512::from(1_000_000_000u64), referrer);
+
+    // Two years at 100% can take no more than the whole position
+    env.advance_block_time(2 * 365 * 24 * 60 * 60 * 1000);
+    contract.deposit(U512::from(1_000_000_000u64));
+
+    assert_eq!(
+        contract.get_position(user).shares,
+        U512::from(500_000_000u64)
+    );
+    assert_eq!(
+        contract.get_position(referrer).shares,
+        U512::from(500_000_000u64)
+    );
+}
+
+#[test]
+fn test_referral_fee_accrues_before_deposit() {
+    let (env, mut contract) = setup();
+    let user = env.get_account(2);
+    contract.set_referral_share(5000);
+
+    env.set_caller(user);
+    contract.deposit_with_referrer(U512::from(1_000_000_000u64), env.get_account(3));
+    env.advance_block_time(365 * 24 * 60 * 60 * 1000);
+
+    // Shares arriving now only pay fees from now on
+    contract.deposit(U512::from(99_000_000_000u64));
+    contract.withdraw(U512::from(1_000_000u64));
+
+    assert_eq!(
+        contract.get_referral_stats(env.get_account(3)).earnings,
+        U512::from(5_000_000u64)
+    );
+}
+
+#[test]
+fn test_existing_user_cannot_be_referred() {
+    let (env, mut contract) = setup();
+    let user = env.get_account(1);
+
+    env.set_caller(user);
+    contract.deposit(U512::from(1_000_000_000u64));
+    contract.deposit_with_referrer(U512::from(1_000_000_000u64), env.get_account(2));
+    assert_eq!(contract.get_referrer(user), None);
+}
+
+#[test]
+#[should_panic(expected = "SelfReferral")]
+fn test_self_referral() {
+    let (env, mut contract) = setup();
+
+    env.set_caller(env.get_account(1));
+    contract.deposit_with_referrer(U512::from(1_000_000_000u64), env.get_account(1));
+}
+
+/// Reward token owned by account 0, 5000 tokens sent to the vault and streamed over 1000 ms
+fn setup_reward_stream() -> (HostEnv, YieldOptimizerHostRef, Cep18HostRef) {
+    let (env, mut contract) = setup();
+
+    let mut token = Cep18::deploy(
+        &env,
+        Cep18InitArgs {
+            symbol: "RWD".to_string(),
+            name: "Reward".to_string(),
+            decimals: 9,
+            initial_supply: U256::from(1_000_000u64),
+        },
+    );
+    token.transfer(&contract.address(), &U256::from(5000u64));
+
+    contract.set_reward_token(token.address());
+
+    env.set_caller(env.get_account(1));
+    contract.deposit(U512::from(1_000_000_000u64));
+    env.set_caller(env.get_account(2));
+    contract.deposit(U512::from(4_000_000_000u64));
+
+    env.set_caller(env.get_account(0));
+    contract.notify_reward_amount(U512::from(5000u64), 1000);
+
+    (env, contract, token)
+}
+
+#[test]
+fn test_rewards_stream_pro_rata() {
+    let (env, contract, _token) = setup_reward_stream();
+
+    env.advance_block_time(500);
+    assert_eq!(
+        contract.pending_rewards(env.get_account(1)),
+        U512::from(500u64)
+    );
+    assert_eq!(
+        contract.pending_rewards(env.get_account(2)),
+        U512::from(2000u64)
+    );
+
+    // Nothing accrues after the period ends
+    env.advance_block_time(1000);
+    assert_eq!(
+        contract.pending_rewards(env.get_account(2)),
+        U512::from(4000u64)
+    );
+}
+
+#[test]
+fn test_withdraw_settles_rewards() {
+    let (env, mut contract, token) = setup_reward_stream();
+    let early = env.get_account(1);
+    let holder = env.get_account(2);
+
+    env.advance_block_time(500);
+    env.set_caller(early);
+    contract.withdraw(U512::from(1_000_000_000u64));
+
+    // The remaining holder gets the whole stream from here on
+    env.advance_block_time(500);
+    assert_eq!(contract.pending_rewards(early), U512::from(500u64));
+    assert_eq!(contract.pending_rewards(holder), U512::from(4500u64));
+
+    contract.claim_rewards();
+    assert_eq!(token.balance_of(&early), U256::from(500u64));
+    assert_eq!(contract.pending_rewards(early), U512::zero());
+
+    env.set_caller(holder);
+    contract.claim_rewards();
+    assert_eq!(token.balance_of(&holder), U256::from(4500u64));
+    assert_eq!(
+        contract.get_position(holder).shares,
+        U512::from(4_000_000_000u64)
+    );
+}
+
+#[test]
+fn test_rewards_stay_in_their_token_after_switch() {
+    let (env, mut contract, token) = setup_reward_stream();
+    let user = env.get_account(1);
+
+    env.advance_block_time(1000);
+    let new_token = Cep18::deploy(
+        &env,
+        Cep18InitArgs {
+            symbol: "NEW".to_string(),
+            name: "New Reward".to_string(),
+            decimals: 9,
+            initial_supply: U256::from(1_000_000u64),
+        },
+    );
+    contract.set_reward_token(new_token.address());
+
+    assert_eq!(contract.pending_rewards(user), U512::zero());
+    assert_eq!(
+        contract.get_claimable_rewards(user, token.address()),
+        U512::from(1000u64)
+    );
+
+    env.set_caller(user);
+    contract.claim_rewards();
+    assert_eq!(token.balance_of(&user), U256::from(1000u64));
+    assert_eq!(new_token.balance_of(&user), U256::zero());
+}
+
+#[test]
+#[should_panic(expected = "InsufficientRewardBalance")]
+fn test_reward_period_must_be_funded() {
+    let (env, mut contract, _token) = setup_reward_stream();
+
+    env.advance_block_time(1000);
+    contract.notify_reward_amount(U512::from(6000u64), 1000);
+}
+
+#[test]
+#[should_panic(expected = "InsufficientRewardBalance")]
+fn test_reward_period_cannot_use_unclaimed_rewards() {
+    let (env, mut contract, mut token) = setup_reward_stream();
+
+    // The first 5000 tokens are owed to holders, only the new 1000 are free
+    env.advance_block_time(1000);
+    token.transfer(&contract.address(), &U256::from(1000u64));
+    contract.notify_reward_amount(U512::from(2000u64), 1000);
+}
+
+#[test]
+fn test_locked_shares_get_boost() {
+    let (env, mut contract, _token) = setup_reward_stream();
+    let locker = env.get_account(2);
+
+    // Locking all 4 shares for half the max duration weighs them as 6
+    env.set_caller(locker);
+    contract.lock(U512::from(4_000_000_000u64), YEAR_MS / 2);
+    assert_eq!(contract.get_lock(locker).unwrap().boost, 5000);
+    assert_eq!(
+        contract.get_boosted_balance(locker),
+        U512::from(6_000_000_000u64)
+    );
+
+    env.advance_block_time(1000);
+    assert_eq!(
+        contract.pending_rewards(env.get_account(1)),
+        U512::from(714u64)
+    );
+    assert_eq!(contract.pending_rewards(locker), U512::from(4285u64));
+}
+
+#[test]
+fn test_lock_boost_ends_at_expiry() {
+    let (env, mut contract, token) = setup_reward_stream();
+    let early = env.get_account(1);
+    let locker = env.get_account(2);
+
+    // Half the max duration: 4 shares weigh 6 for the first 500 ms only
+    contract.set_max_lock_duration(1000);
+    env.set_caller(locker);
+    contract.lock(U512::from(4_000_000_000u64), 500);
+
+    env.advance_block_time(800);
+    env.set_caller(early);
+    contract.claim_rewards();
+    env.advance_block_time(200);
+
+    assert_eq!(
+        contract.get_boosted_balance(locker),
+        U512::from(4_000_000_000u64)
+    );
+    // 2500 split 1:6 over the first 500 ms, then 2500 split 1:4
+    assert_eq!(contract.pending_rewards(locker), U512::from(4142u64));
+    let early_total =
+        U512::from(token.balance_of(&early).as_u64()) + contract.pending_rewards(early);
+    assert_eq!(early_total, U512::from(857u64));
+
+    // The whole period is paid out, apart from rounding
+    env.set_caller(locker);
+    contract.claim_rewards();
+    assert!(contract.get_lock(locker).is_none());
+    assert!(early_total + U512::from(token.balance_of(&locker).as_u64()) >= U512::from(4998u64));
+}
+
+#[test]
+#[should_panic(expected = "SharesLocked")]
+fn test_locked_shares_cannot_be_withdrawn() {
+    let (env, mut contract) = setup();
+
+    contract.deposit(U512::from(1_000_000_000u64));
+    contract.lock(U512::from(600_000_000u64), 60_000);
+
+    // Unlocked shares stay free
+    contract.withdraw(U512::from(400_000_000u64));
+    env.advance_block_time(59_999);
+    contract.withdraw(U512::from(1u64));
+}
+
+#[test]
+fn test_lock_expiry_and_extension() {
+    let (env, mut contract) = setup();
+    let user = env.get_account(0);
+
+    contract.deposit(U512::from(1_000_000_000u64));
+    contract.lock(U512::from(1_000_000_000u64), 60_000);
+    contract.extend_lock(120_000);
+    assert_eq!(contract.get_lock(user).unwrap().unlock_at, 120_000);
+
+    env.advance_block_time(120_000);
+    contract.unlock(user);
+    assert!(contract.get_lock(user).is_none());
+    assert_eq!(
+        contract.get_boosted_balance(user),
+        U512::from(1_000_000_000u64)
+    );
+    // Two minutes of the 1% management fee come off on withdrawal
+    contract.withdraw(U512::from(999_999_962u64));
+}
+
+#[test]
+#[should_panic(expected = "LockNotExpired")]
+fn test_unlock_before_expiry() {
+    let (env, mut contract) = setup();
+
+    contract.deposit(U512::from(1_000_000_000u64));
+    contract.lock(U512::from(1_000_000_000u64), 60_000);
+    contract.unlock(env.get_account(0));
 }
 
 #[test]
@@ -189,7 +1876,7 @@ fn test_update_pool_apy() {
 }
 
 #[test]
-#[should_panic(expected = "Pool not found")]
+#[should_panic(expected = "PoolNotFound")]
 fn test_update_nonexistent_pool() {
     let (env, mut contract) = setup();
 
@@ -220,7 +1907,7 @@ fn test_allocate_to_pool() {
 }
 
 #[test]
-#[should_panic(expected = "Insufficient TVL")]
+#[should_panic(expected = "InsufficientTvl")]
 fn test_allocate_exceeds_tvl() {
     let (env, mut contract) = setup();
 
@@ -267,7 +1954,7 @@ fn test_rebalance_pools() {
 }
 
 #[test]
-#[should_panic(expected = "Insufficient allocation in source pool")]
+#[should_panic(expected = "InsufficientAllocation")]
 fn test_rebalance_insufficient_allocation() {
     let (env, mut contract) = setup();
 
@@ -353,15 +2040,15 @@ fn test_pause_unpause() {
 
     // Pause contract
     contract.pause();
-    assert_eq!(contract.is_paused(), true);
+    assert!(contract.is_paused().paused);
 
     // Unpause contract
     contract.unpause();
-    assert_eq!(contract.is_paused(), false);
+    assert!(!contract.is_paused().paused);
 }
 
 #[test]
-#[should_panic(expected = "Contract is paused")]
+#[should_panic(expected = "ContractPaused")]
 fn test_deposit_when_paused() {
     let (env, mut contract) = setup();
 
@@ -377,7 +2064,313 @@ fn test_deposit_when_paused() {
 }
 
 #[test]
-#[should_panic(expected = "Caller is not owner")]
+fn test_withdraw_when_only_deposits_paused() {
+    let (env, mut contract) = setup();
+
+    let owner = env.get_account(0);
+    let user = env.get_account(1);
+
+    env.set_caller(user);
+    contract.deposit(U512::from(1_000_000_000u64));
+
+    env.set_caller(owner);
+    contract.set_pause_flags(true, false, false, false, "Oracle incident".to_string());
+
+    let state = contract.get_pause_state();
+    assert!(state.deposits_paused);
+    assert!(!state.withdrawals_paused);
+    assert_eq!(state.reason, "Oracle incident");
+    assert_eq!(state.paused_at, env.block_time());
+    assert!(env.emitted(&contract, "PauseStateChanged"));
+
+    env.set_caller(user);
+    contract.withdraw(U512::from(1_000_000_000u64));
+    assert_eq!(contract.get_tvl(), U512::zero());
+}
+
+#[test]
+#[should_panic(expected = "ContractPaused")]
+fn test_withdraw_when_withdrawals_paused() {
+    let (env, mut contract) = setup();
+
+    let owner = env.get_account(0);
+    let user = env.get_account(1);
+
+    env.set_caller(user);
+    contract.deposit(U512::from(1_000_000_000u64));
+
+    env.set_caller(owner);
+    contract.set_pause_flags(false, true, false, false, "Investigating".to_string());
+
+    env.set_caller(user);
+    contract.withdraw(U512::from(1_000_000_000u64));
+}
+
+#[test]
+#[should_panic(expected = "ContractPaused")]
+fn test_allocate_when_allocations_paused() {
+    let (env, mut contract) = setup();
+
+    let owner = env.get_account(0);
+    let user = env.get_account(1);
+
+    env.set_caller(user);
+    contract.deposit(U512::from(10_000_000_000u64));
+
+    env.set_caller(owner);
+    contract.add_pool("Pool A".to_string(), 1250, 2);
+    contract.set_pause_flags(false, false, true, false, "Pool audit".to_string());
+    contract.allocate_to_pool("Pool A".to_string(), U512::from(5_000_000_000u64));
+}
+
+#[test]
+fn test_emergency_mode_keeps_withdrawals_open() {
+    let (env, mut contract) = setup();
+
+    let owner = env.get_account(0);
+    let user = env.get_account(1);
+
+    env.set_caller(user);
+    contract.deposit(U512::from(1_000_000_000u64));
+
+    // Even with the withdrawal flag set, emergency mode lets users exit
+    env.set_caller(owner);
+    contract.pause();
+    contract.enter_emergency_mode("Pool exploit".to_string());
+    assert!(contract.get_pause_state().emergency_mode);
+
+    env.set_caller(user);
+    contract.withdraw(U512::from(1_000_000_000u64));
+    assert_eq!(contract.get_position(user).shares, U512::zero());
+}
+
+#[test]
+#[should_panic(expected = "ContractPaused")]
+fn test_deposit_in_emergency_mode() {
+    let (env, mut contract) = setup();
+
+    env.set_caller(env.get_account(0));
+    contract.enter_emergency_mode("Pool exploit".to_string());
+
+    env.set_caller(env.get_account(1));
+    contract.deposit(U512::from(1_000_000_000u64));
+}
+
+#[test]
+fn test_pause_expires_after_max_duration() {
+    let (env, mut contract) = setup();
+
+    let owner = env.get_account(0);
+    let user = env.get_account(1);
+
+    env.set_caller(owner);
+    contract.set_max_pause_duration(3_600_000); // 1 hour
+    contract.pause();
+
+    let status = contract.is_paused();
+    assert!(status.paused);
+    assert_eq!(status.expires_at, Some(env.block_time() + 3_600_000));
+
+    env.advance_block_time(3_600_000);
+    let status = contract.is_paused();
+    assert!(!status.paused);
+    assert_eq!(status.expires_at, None);
+
+    // Operations resume without an explicit unpause
+    env.set_caller(user);
+    contract.deposit(U512::from(1_000_000_000u64));
+    assert_eq!(contract.get_tvl(), U512::from(1_000_000_000u64));
+}
+
+#[test]
+fn test_pause_without_max_duration_never_expires() {
+    let (env, mut contract) = setup();
+
+    env.set_caller(env.get_account(0));
+    contract.pause();
+    env.advance_block_time(365 * 24 * 3_600_000);
+
+    let status = contract.is_paused();
+    assert!(status.paused);
+    assert_eq!(status.expires_at, None);
+}
+
+#[test]
+fn test_pause_extension_requires_every_signer() {
+    let (env, mut contract) = setup_committee();
+
+    env.set_caller(env.get_account(0));
+    let proposal_id = contract.propose(
+        "set_max_pause_duration".to_string(),
+        call_args(runtime_args! { "duration" => 3_600_000u64 }),
+    );
+    env.set_caller(env.get_account(1));
+    contract.approve(proposal_id);
+    let proposal_id = contract.propose("pause".to_string(), call_args(runtime_args! {}));
+    env.set_caller(env.get_account(2));
+    contract.approve(proposal_id);
+
+    let until = env.block_time() + 7_200_000;
+    env.set_caller(env.get_account(0));
+    let proposal_id = contract.propose(
+        "extend_pause".to_string(),
+        call_args(runtime_args! { "until" => until }),
+    );
+
+    // The regular 2-of-3 threshold is not enough
+    env.set_caller(env.get_account(1));
+    contract.approve(proposal_id);
+    assert!(!contract.get_proposal(proposal_id).unwrap().executed);
+
+    env.set_caller(env.get_account(2));
+    contract.approve(proposal_id);
+    assert_eq!(contract.is_paused().expires_at, Some(until));
+
+    env.advance_block_time(3_600_000);
+    assert!(contract.is_paused().paused);
+    env.advance_block_time(3_600_000);
+    assert!(!contract.is_paused().paused);
+}
+
+#[test]
+fn test_repause_after_expiry_requires_every_signer() {
+    let (env, mut contract) = setup_committee();
+
+    env.set_caller(env.get_account(0));
+    let proposal_id = contract.propose(
+        "set_max_pause_duration".to_string(),
+        call_args(runtime_args! { "duration" => 3_600_000u64 }),
+    );
+    env.set_caller(env.get_account(1));
+    contract.approve(proposal_id);
+    let proposal_id = contract.propose("pause".to_string(), call_args(runtime_args! {}));
+    env.set_caller(env.get_account(2));
+    contract.approve(proposal_id);
+
+    env.advance_block_time(3_600_000);
+    assert!(!contract.is_paused().paused);
+
+    // A fresh window right after expiry is an extension in disguise
+    env.set_caller(env.get_account(0));
+    let proposal_id = contract.propose("pause".to_string(), call_args(runtime_args! {}));
+    env.set_caller(env.get_account(1));
+    contract.approve(proposal_id);
+    assert!(!contract.is_paused().paused);
+
+    env.set_caller(env.get_account(2));
+    contract.approve(proposal_id);
+    assert!(contract.is_paused().paused);
+}
+
+#[test]
+#[should_panic(expected = "NotCommittee")]
+fn test_repause_after_unpause_requires_committee() {
+    let (env, mut contract) = setup();
+
+    env.set_caller(env.get_account(0));
+    contract.set_max_pause_duration(3_600_000);
+    contract.pause();
+    contract.unpause();
+    contract.pause();
+}
+
+#[test]
+#[should_panic(expected = "NotCommittee")]
+fn test_disabling_max_pause_duration_during_pause_requires_committee() {
+    let (env, mut contract) = setup();
+
+    env.set_caller(env.get_account(0));
+    contract.set_max_pause_duration(3_600_000);
+    contract.pause();
+    contract.set_max_pause_duration(0);
+}
+
+#[test]
+#[should_panic(expected = "NotCommittee")]
+fn test_extend_pause_requires_committee() {
+    let (env, mut contract) = setup();
+
+    env.set_caller(env.get_account(0));
+    contract.set_max_pause_duration(3_600_000);
+    contract.pause();
+    contract.extend_pause(env.block_time() + 7_200_000);
+}
+
+#[test]
+fn test_emergency_exit_unwinds_pools() {
+    let (env, mut contract) = setup();
+
+    let owner = env.get_account(0);
+    let user = env.get_account(1);
+
+    env.set_caller(user);
+    contract.deposit(U512::from(10_000_000_000u64));
+
+    env.set_caller(owner);
+    contract.add_pool("Pool A".to_string(), 1250, 2);
+    contract.add_pool("Pool B".to_string(), 1500, 3);
+    contract.allocate_to_pool("Pool A".to_string(), U512::from(4_000_000_000u64));
+    contract.allocate_to_pool("Pool B".to_string(), U512::from(3_000_000_000u64));
+    assert_eq!(contract.get_idle_liquidity(), U512::from(3_000_000_000u64));
+
+    contract.emergency_exit();
+
+    assert!(contract.is_exited());
+    assert_eq!(contract.get_total_allocated(), U512::zero());
+    assert_eq!(contract.get_idle_liquidity(), U512::from(10_000_000_000u64));
+    for name in contract.get_pool_names() {
+        assert_eq!(
+            contract.get_pool_info(name).unwrap().total_allocated,
+            U512::zero()
+        );
+    }
+
+    let event: EmergencyExit = env.get_event(&contract, -1).unwrap();
+    assert_eq!(
+        event.recovered,
+        vec![
+            ("Pool A".to_string(), U512::from(4_000_000_000u64)),
+            ("Pool B".to_string(), U512::from(3_000_000_000u64)),
+        ]
+    );
+    assert_eq!(event.total_recovered, U512::from(7_000_000_000u64));
+    assert_eq!(event.loss, U512::zero());
+}
+
+#[test]
+fn test_withdraw_after_emergency_exit() {
+    let (env, mut contract) = setup();
+
+    let owner = env.get_account(0);
+    let user = env.get_account(1);
+
+    env.set_caller(user);
+    contract.deposit(U512::from(1_000_000_000u64));
+
+    env.set_caller(owner);
+    contract.pause();
+    contract.emergency_exit();
+
+    // Pause flags do not lock holders out of an exited vault
+    env.set_caller(user);
+    contract.withdraw(U512::from(1_000_000_000u64));
+    assert_eq!(contract.get_tvl(), U512::zero());
+}
+
+#[test]
+#[should_panic(expected = "VaultExited")]
+fn test_deposit_after_emergency_exit() {
+    let (env, mut contract) = setup();
+
+    env.set_caller(env.get_account(0));
+    contract.emergency_exit();
+
+    env.set_caller(env.get_account(1));
+    contract.deposit(U512::from(1_000_000_000u64));
+}
+
+#[test]
+#[should_panic(expected = "NotOwner")]
 fn test_non_owner_cannot_pause() {
     let (env, mut contract) = setup();
 
@@ -388,7 +2381,7 @@ fn test_non_owner_cannot_pause() {
 }
 
 #[test]
-#[should_panic(expected = "Caller is not owner")]
+#[should_panic(expected = "NotOwner")]
 fn test_non_owner_cannot_add_pool() {
     let (env, mut contract) = setup();
 
@@ -417,5 +2410,290 @@ fn test_owner_is_set_correctly() {
     let (env, contract) = setup();
 
     let owner = env.get_account(0);
-    assert_eq!(contract.get_owner(), owner);
+    assert_eq!(contract.get_owner(), Some(owner));
+}
+
+#[test]
+fn test_deployer_is_initial_signer() {
+    let (env, contract) = setup();
+
+    let owner = env.get_account(0);
+    assert_eq!(contract.get_signers(), vec![owner]);
+    assert_eq!(contract.get_threshold(), 1);
+}
+
+#[test]
+fn test_committee_setup() {
+    let (env, contract) = setup_committee();
+
+    assert_eq!(
+        contract.get_signers(),
+        vec![env.get_account(0), env.get_account(1), env.get_account(2)]
+    );
+    assert_eq!(contract.get_threshold(), 2);
+    assert_eq!(contract.get_proposal_count(), 3);
+}
+
+#[test]
+fn test_committee_proposal_executes_at_threshold() {
+    let (env, mut contract) = setup_committee();
+
+    env.set_caller(env.get_account(1));
+    let proposal_id = contract.propose("pause".to_string(), call_args(runtime_args! {}));
+
+    // One approval out of two: nothing happens yet
+    assert!(!contract.is_paused().paused);
+    let proposal = contract.get_proposal(proposal_id).unwrap();
+    assert_eq!(proposal.approvals, 1);
+    assert!(!proposal.executed);
+
+    env.set_caller(env.get_account(2));
+    contract.approve(proposal_id);
+
+    assert!(contract.is_paused().paused);
+    assert!(contract.get_proposal(proposal_id).unwrap().executed);
+}
+
+#[test]
+fn test_committee_proposal_with_arguments() {
+    let (env, mut contract) = setup_committee();
+
+    env.set_caller(env.get_account(0));
+    let proposal_id = contract.propose(
+        "add_pool".to_string(),
+        call_args(runtime_args! {
+            "name" => "Pool A".to_string(),
+            "initial_apy" => 1250u32,
+            "risk_level" => 2u8,
+        }),
+    );
+    assert!(contract.get_pool_info("Pool A".to_string()).is_none());
+
+    env.set_caller(env.get_account(2));
+    contract.approve(proposal_id);
+
+    let pool = contract.get_pool_info("Pool A".to_string()).unwrap();
+    assert_eq!(pool.current_apy, 1250);
+}
+
+/// Accounts 1 and 2 hold 1 and 3 shares, account 1 proposes a 2% management fee
+fn setup_governance() -> (HostEnv, YieldOptimizerHostRef, u32) {
+    let (env, mut contract) = setup();
+
+    env.set_caller(env.get_account(1));
+    contract.deposit(U512::from(1_000_000_000u64));
+    env.set_caller(env.get_account(2));
+    contract.deposit(U512::from(3_000_000_000u64));
+    env.advance_block_time(1);
+
+    env.set_caller(env.get_account(1));
+    let proposal_id = contract.propose_governance(
+        "set_management_fee".to_string(),
+        call_args(runtime_args! { "fee_bp" => 200u32 }),
+    );
+    (env, contract, proposal_id)
+}
+
+#[test]
+fn test_governance_proposal_executes_after_vote() {
+    let (env, mut contract, proposal_id) = setup_governance();
+
+    env.set_caller(env.get_account(1));
+    contract.vote(proposal_id, false);
+    env.set_caller(env.get_account(2));
+    contract.vote(proposal_id, true);
+
+    let proposal = contract.get_governance_proposal(proposal_id).unwrap();
+    assert_eq!(proposal.votes_for, U512::from(3_000_000_000u64));
+    assert_eq!(proposal.votes_against, U512::from(1_000_000_000u64));
+    assert!(contract.is_governance_proposal_passed(proposal_id));
+
+    let (voting_period, _) = contract.get_governance_config();
+    env.advance_block_time(voting_period);
+    contract.execute_governance_proposal(proposal_id);
+    assert_eq!(contract.get_management_fee(), 200);
+}
+
+#[test]
+#[should_panic(expected = "VotingActive")]
+fn test_governance_proposal_waits_for_voting_end() {
+    let (env, mut contract, proposal_id) = setup_governance();
+
+    env.set_caller(env.get_account(2));
+    contract.vote(proposal_id, true);
+    contract.execute_governance_proposal(proposal_id);
+}
+
+#[test]
+fn test_governance_votes_use_snapshot() {
+    let (env, mut contract, proposal_id) = setup_governance();
+    let (holder, receiver) = (env.get_account(2), env.get_account(3));
+    let created_at = contract
+        .get_governance_proposal(proposal_id)
+        .unwrap()
+        .created_at;
+
+    // Shares moved after the proposal carry no votes on it
+    env.set_caller(holder);
+    contract.withdraw(U512::from(3_000_000_000u64));
+    contract.vote(proposal_id, true);
+    env.set_caller(receiver);
+    contract.deposit(U512::from(3_000_000_000u64));
+    assert_eq!(
+        contract.get_past_shares(holder, created_at),
+        U512::from(3_000_000_000u64)
+    );
+    assert_eq!(contract.get_past_shares(receiver, created_at), U512::zero());
+    assert_eq!(
+        contract.get_past_total_shares(created_at),
+        U512::from(4_000_000_000u64)
+    );
+
+    assert!(contract.try_vote(proposal_id, true).is_err());
+
+    // The next proposal sees the new balances
+    env.advance_block_time(1);
+    let next_id = contract.propose_governance(
+        "set_management_fee".to_string(),
+        call_args(runtime_args! { "fee_bp" => 300u32 }),
+    );
+    let next_at = contract
+        .get_governance_proposal(next_id)
+        .unwrap()
+        .created_at;
+    assert_eq!(
+        contract.get_past_shares(receiver, next_at),
+        U512::from(3_000_000_000u64)
+    );
+    assert_eq!(contract.get_past_shares(holder, next_at), U512::zero());
+}
+
+#[test]
+#[should_panic(expected = "NoVotingPower")]
+fn test_governance_ignores_shares_from_the_proposal_block() {
+    let (env, mut contract, proposal_id) = setup_governance();
+
+    env.set_caller(env.get_account(3));
+    contract.deposit(U512::from(5_000_000_000u64));
+    contract.vote(proposal_id, true);
+}
+
+#[test]
+#[should_panic(expected = "AddressBlocked")]
+fn test_blocked_address_cannot_vote() {
+    let (env, mut contract, proposal_id) = setup_governance();
+
+    let guardian = env.get_account(4);
+    env.set_caller(env.get_account(0));
+    contract.set_guardian(guardian, true);
+    env.set_caller(guardian);
+    contract.block_address(env.get_account(2));
+
+    env.set_caller(env.get_account(2));
+    contract.vote(proposal_id, true);
+}
+
+#[test]
+#[should_panic(expected = "QuorumNotReached")]
+fn test_governance_quorum() {
+    let (env, mut contract, proposal_id) = setup_governance();
+    env.set_caller(env.get_account(0));
+    contract.set_governance_config(DEFAULT_VOTING_PERIOD, 5000);
+
+    // 1 of 4 shares is below a 50% quorum
+    env.set_caller(env.get_account(1));
+    contract.vote(proposal_id, true);
+    env.advance_block_time(DEFAULT_VOTING_PERIOD);
+    contract.execute_governance_proposal(proposal_id);
+}
+
+#[test]
+#[should_panic(expected = "ActionNotGovernable")]
+fn test_governance_rejects_other_entry_points() {
+    let (env, mut contract, _) = setup_governance();
+
+    env.set_caller(env.get_account(1));
+    contract.propose_governance("pause".to_string(), call_args(runtime_args! {}));
+}
+
+#[test]
+#[should_panic(expected = "NotOwner")]
+fn test_signer_cannot_bypass_committee() {
+    let (env, mut contract) = setup_committee();
+
+    env.set_caller(env.get_account(0));
+    contract.pause();
+}
+
+#[test]
+#[should_panic(expected = "NotSigner")]
+fn test_non_signer_cannot_propose() {
+    let (env, mut contract) = setup_committee();
+
+    env.set_caller(env.get_account(3));
+    contract.propose("pause".to_string(), call_args(runtime_args! {}));
+}
+
+#[test]
+#[should_panic(expected = "AlreadyApproved")]
+fn test_signer_cannot_approve_twice() {
+    let (env, mut contract) = setup_committee();
+
+    env.set_caller(env.get_account(1));
+    let proposal_id = contract.propose("pause".to_string(), call_args(runtime_args! {}));
+    contract.approve(proposal_id);
+}
+
+#[test]
+#[should_panic(expected = "ProposalAlreadyExecuted")]
+fn test_executed_proposal_cannot_be_approved() {
+    let (env, mut contract) = setup_committee();
+
+    env.set_caller(env.get_account(1));
+    let proposal_id = contract.propose("pause".to_string(), call_args(runtime_args! {}));
+    env.set_caller(env.get_account(2));
+    contract.approve(proposal_id);
+
+    env.set_caller(env.get_account(0));
+    contract.approve(proposal_id);
+}
+
+#[test]
+#[should_panic(expected = "NotCommittee")]
+fn test_signer_set_changes_require_committee() {
+    let (env, mut contract) = setup();
+
+    // Even a 1-of-1 committee must go through a proposal
+    env.set_caller(env.get_account(0));
+    contract.add_signer(env.get_account(1));
+}
+
+#[test]
+fn test_committee_removes_signer() {
+    let (env, mut contract) = setup_committee();
+
+    env.set_caller(env.get_account(0));
+    let proposal_id = contract.propose(
+        "remove_signer".to_string(),
+        call_args(runtime_args! { "signer" => env.get_account(2) }),
+    );
+    env.set_caller(env.get_account(1));
+    contract.approve(proposal_id);
+
+    assert!(!contract.is_signer(env.get_account(2)));
+    assert_eq!(contract.get_signers().len(), 2);
+}
+
+#[test]
+#[should_panic(expected = "InvalidThreshold")]
+fn test_threshold_cannot_exceed_signers() {
+    let (env, mut contract) = setup_committee();
+
+    env.set_caller(env.get_account(0));
+    let proposal_id = contract.propose(
+        "set_threshold".to_string(),
+        call_args(runtime_args! { "threshold" => 4u32 }),
+    );
+    env.set_caller(env.get_account(1));
+    contract.approve(proposal_id);
 }
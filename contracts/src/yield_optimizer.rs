@@ -1,5 +1,44 @@
+// Event constructors generated by #[odra::event] take one argument per field
+#![allow(clippy::too_many_arguments)]
+
+use odra::casper_types::bytesrepr::{Bytes, FromBytes, ToBytes};
+use odra::casper_types::{PublicKey, RuntimeArgs, U256, U512};
 use odra::prelude::*;
-use odra::casper_types::U512;
+use odra::{CallDef, ContractRef};
+
+/// Delay between queueing and executing a blocked position recovery (2 days)
+const POSITION_RECOVERY_DELAY: u64 = 2 * 24 * 60 * 60 * 1000;
+
+/// Default time undelegated stake stays locked (7 eras of 2 hours)
+const DEFAULT_UNBONDING_PERIOD: u64 = 7 * 2 * 60 * 60 * 1000;
+
+/// Fixed-point scale of the price per share (1 CSPR in motes)
+const PRICE_PRECISION: u64 = 1_000_000_000;
+
+/// Number of price-per-share checkpoints kept in the ring buffer
+const PPS_HISTORY_SIZE: u64 = 256;
+
+/// Number of APY checkpoints kept per pool
+const APY_HISTORY_SIZE: u64 = 128;
+
+/// Fixed-point scale of the accumulated reward per share
+const REWARD_PRECISION: u64 = 1_000_000_000_000_000_000;
+
+/// Entry points shareholders can call through governance proposals
+const GOVERNABLE_ENTRY_POINTS: [&str; 3] =
+    ["add_pool", "set_validator_target", "set_management_fee"];
+
+/// Default length of a governance vote (3 days)
+const DEFAULT_VOTING_PERIOD: u64 = 3 * 24 * 60 * 60 * 1000;
+
+/// Default share of the snapshot supply that must vote for a proposal (10%)
+const DEFAULT_QUORUM_BP: u32 = 1000;
+
+/// Extra reward weight of shares locked for the maximum duration (+100%)
+const MAX_LOCK_BOOST: u32 = 10000;
+
+/// Milliseconds in a 365-day year
+const YEAR_MS: u64 = 365 * 24 * 60 * 60 * 1000;
 
 /// Custom errors for the contract
 #[odra::odra_error]
@@ -12,23 +51,104 @@ pub enum Error {
     PoolNotFound = 6,
     InsufficientTvl = 7,
     InsufficientAllocation = 8,
+    NotSigner = 9,
+    ProposalNotFound = 10,
+    AlreadyApproved = 11,
+    ProposalAlreadyExecuted = 12,
+    InvalidThreshold = 13,
+    SignerAlreadyExists = 14,
+    SignerNotFound = 15,
+    NotCommittee = 16,
+    InvalidProposalArgs = 17,
+    NotPaused = 18,
+    InvalidPauseExtension = 19,
+    VaultExited = 20,
+    PoolAlreadyExists = 21,
+    TvlCapExceeded = 22,
+    UserDepositCapExceeded = 23,
+    DepositBelowMinimum = 24,
+    NotAllowlisted = 25,
+    NotGuardian = 26,
+    AddressBlocked = 27,
+    AddressNotBlocked = 28,
+    RecoveryNotQueued = 29,
+    RecoveryTimelocked = 30,
+    NotKeeper = 31,
+    WithdrawalRequestNotFound = 32,
+    WithdrawalNotProcessed = 33,
+    WithdrawalAlreadyClaimed = 34,
+    NotRequestOwner = 35,
+    CooldownNotStarted = 36,
+    CooldownNotElapsed = 37,
+    CooldownWindowClosed = 38,
+    CooldownSharesExceeded = 39,
+    InvalidCooldownConfig = 40,
+    DelegationRewardsNotDeclarable = 41,
+    NotDelegationPool = 42,
+    InvalidValidatorTarget = 43,
+    InvalidManagementFee = 44,
+    ApyChangeTooLarge = 45,
+    StaleApy = 46,
+    NotOperator = 47,
+    PermitExpired = 48,
+    InvalidSignature = 49,
+    SelfReferral = 50,
+    InvalidReferralShare = 51,
+    RewardTokenNotSet = 52,
+    RewardPeriodActive = 53,
+    InvalidRewardPeriod = 54,
+    InsufficientRewardBalance = 55,
+    LockExists = 56,
+    NoLock = 57,
+    InvalidLockDuration = 58,
+    SharesLocked = 59,
+    LockNotExpired = 60,
+    ActionNotGovernable = 61,
+    NoVotingPower = 62,
+    AlreadyVoted = 63,
+    VotingClosed = 64,
+    VotingActive = 65,
+    QuorumNotReached = 66,
+    InvalidGovernanceConfig = 67,
+    AmountOverflow = 68,
+}
+
+/// CEP-18 token used for streamed rewards
+#[odra::external_contract]
+pub trait Cep18Token {
+    fn transfer(&mut self, recipient: &Address, amount: &U256);
+    fn balance_of(&self, address: &Address) -> U256;
 }
 
 /// Events emitted by the contract
 #[odra::event]
 pub struct Deposit {
-    pub user: Address,
+    pub user: Address, // Same as owner, kept for indexers
+    pub caller: Address,
+    pub owner: Address,    // Credited with the shares
+    pub receiver: Address, // Receives the shares, same as owner
     pub amount: U512,
     pub shares: U512,
+    pub user_shares: U512, // Post-state
+    pub total_tvl: U512,
+    pub total_shares: U512,
     pub timestamp: u64,
+    pub nonce: u64,
 }
 
 #[odra::event]
 pub struct Withdrawal {
-    pub user: Address,
+    pub user: Address, // Same as owner, kept for indexers
+    pub caller: Address,
+    pub owner: Address, // Debited the shares
+    pub receiver: Address,
     pub amount: U512,
     pub shares: U512,
+    pub user_shares: U512, // Post-state
+    pub total_tvl: U512,
+    pub total_shares: U512,
     pub timestamp: u64,
+    pub nonce: u64,
 }
 
 #[odra::event]
@@ -37,362 +157,4459 @@ pub struct Rebalance {
     pub to_pool: String,
     pub amount: U512,
     pub timestamp: u64,
+    pub nonce: u64,
 }
 
 #[odra::event]
 pub struct RewardsHarvested {
     pub pool: String,
     pub amount: U512,
+    pub price_per_share: U512, // Post-state, scaled by PRICE_PRECISION
     pub timestamp: u64,
+    pub nonce: u64,
 }
 
-/// User position information
-#[odra::odra_type]
-pub struct UserPosition {
+#[odra::event]
+pub struct PoolAdded {
+    pub pool: String,
+    pub initial_apy: u32,
+    pub risk_level: u8,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct PoolApyUpdated {
+    pub pool: String,
+    pub old_apy: u32,
+    pub new_apy: u32,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct PoolAllocated {
+    pub pool: String,
+    pub amount: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct Paused {
+    pub reason: String,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct Unpaused {
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct ManagementFeeUpdated {
+    pub old_fee: u32,
+    pub new_fee: u32,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct ProposalCreated {
+    pub proposal_id: u32,
+    pub proposer: Address,
+    pub entry_point: String,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct ProposalApproved {
+    pub proposal_id: u32,
+    pub signer: Address,
+    pub approvals: u32,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct ProposalExecuted {
+    pub proposal_id: u32,
+    pub entry_point: String,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct GovernanceProposalCreated {
+    pub proposal_id: u32,
+    pub proposer: Address,
+    pub entry_point: String,
+    pub voting_ends_at: u64,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct VoteCast {
+    pub proposal_id: u32,
+    pub voter: Address,
+    pub support: bool,
+    pub weight: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct GovernanceProposalExecuted {
+    pub proposal_id: u32,
+    pub entry_point: String,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct GovernanceConfigUpdated {
+    pub voting_period: u64,
+    pub quorum_bp: u32,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct SignerAdded {
+    pub signer: Address,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct SignerRemoved {
+    pub signer: Address,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct ThresholdChanged {
+    pub old_threshold: u32,
+    pub new_threshold: u32,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct PauseStateChanged {
+    pub deposits_paused: bool,
+    pub withdrawals_paused: bool,
+    pub allocations_paused: bool,
+    pub harvests_paused: bool,
+    pub emergency_mode: bool,
+    pub reason: String,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct PauseExtended {
+    pub expires_at: u64,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct MaxPauseDurationUpdated {
+    pub old_duration: u64,
+    pub new_duration: u64,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct EmergencyExit {
+    pub recovered: Vec<(String, U512)>, // Amount recovered per pool
+    pub total_recovered: U512,
+    pub loss: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct LimitsUpdated {
+    pub max_tvl: U512,
+    pub max_deposit_per_user: U512,
+    pub min_deposit: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct CooldownConfigUpdated {
+    pub cooldown_period: u64,
+    pub withdrawal_window: u64,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct CooldownStarted {
+    pub user: Address,
     pub shares: U512,
-    pub deposited_amount: U512,
-    pub last_deposit_time: u64,
-    pub total_rewards: U512,
+    pub withdrawable_from: u64,
+    pub withdrawable_until: u64,
+    pub timestamp: u64,
+    pub nonce: u64,
 }
 
-impl Default for UserPosition {
-    fn default() -> Self {
-        Self {
-            shares: U512::zero(),
-            deposited_amount: U512::zero(),
-            last_deposit_time: 0,
-            total_rewards: U512::zero(),
-        }
-    }
+#[odra::event]
+pub struct AllowlistUpdated {
+    pub account: Address,
+    pub allowed: bool,
+    pub timestamp: u64,
+    pub nonce: u64,
 }
 
-/// Pool information
-#[odra::odra_type]
-pub struct PoolInfo {
-    pub name: String,
-    pub total_allocated: U512,
-    pub current_apy: u32, // APY in basis points (e.g., 1250 = 12.50%)
-    pub risk_level: u8,   // 1-5 (1 = lowest risk)
+#[odra::event]
+pub struct AllowlistToggled {
+    pub enabled: bool,
+    pub timestamp: u64,
+    pub nonce: u64,
 }
 
-/// Main Yield Optimizer Contract
-#[odra::module]
-pub struct YieldOptimizer {
-    /// Owner of the contract
-    owner: Var<Address>,
-    /// Total value locked in the optimizer
-    total_tvl: Var<U512>,
-    /// Total shares issued
-    total_shares: Var<U512>,
-    /// Mapping of user addresses to their positions
-    user_positions: Mapping<Address, UserPosition>,
-    /// Available liquidity pools
-    pools: Mapping<String, PoolInfo>,
-    /// Management fee (in basis points)
-    management_fee: Var<u32>,
-    /// Emergency pause flag
-    paused: Var<bool>,
+#[odra::event]
+pub struct SharesTransferred {
+    pub from: Address,
+    pub to: Address,
+    pub shares: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
 }
 
-#[odra::module]
-impl YieldOptimizer {
-    /// Initialize the contract
-    pub fn init(&mut self, management_fee_bp: u32) {
-        let caller = self.env().caller();
-        self.owner.set(caller);
-        self.total_tvl.set(U512::zero());
-        self.total_shares.set(U512::zero());
-        self.management_fee.set(management_fee_bp);
-        self.paused.set(false);
-    }
+#[odra::event]
+pub struct OperatorSet {
+    pub owner: Address,
+    pub operator: Address,
+    pub approved: bool,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
 
-    /// Deposit funds into the yield optimizer
-    pub fn deposit(&mut self, amount: U512) {
-        self.require_not_paused();
-        self.require_positive_amount(amount);
+#[odra::event]
+pub struct PermitUsed {
+    pub owner: Address,
+    pub action: String,
+    pub permit_nonce: u64,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
 
-        let caller = self.env().caller();
-        let shares = self.calculate_shares(amount);
+#[odra::event]
+pub struct ReferralRecorded {
+    pub user: Address,
+    pub referrer: Address,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
 
-        // Update user position
-        let mut position = self.get_user_position(&caller);
-        position.shares = position.shares + shares;
-        position.deposited_amount = position.deposited_amount + amount;
-        position.last_deposit_time = self.env().get_block_time();
+#[odra::event]
+pub struct ManagementFeeCharged {
+    pub user: Address,
+    pub referrer: Option<Address>,
+    pub fee_shares: U512,
+    pub referrer_shares: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
 
-        self.user_positions.set(&caller, position);
+#[odra::event]
+pub struct ReferralShareUpdated {
+    pub share_bp: u32,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
 
-        // Update global state
-        let current_tvl = self.total_tvl.get().unwrap_or(U512::zero());
-        self.total_tvl.set(current_tvl + amount);
+#[odra::event]
+pub struct RewardTokenSet {
+    pub token: Address,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
 
-        let current_shares = self.total_shares.get().unwrap_or(U512::zero());
-        self.total_shares.set(current_shares + shares);
+#[odra::event]
+pub struct RewardPeriodStarted {
+    pub reward: U512,
+    pub reward_rate: U512,
+    pub period_finish: u64,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct RewardsClaimed {
+    pub user: Address,
+    pub token: Address,
+    pub amount: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct LockCreated {
+    pub user: Address,
+    pub shares: U512,
+    pub unlock_at: u64,
+    pub boost: u32,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct LockExtended {
+    pub user: Address,
+    pub unlock_at: u64,
+    pub boost: u32,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct LockReleased {
+    pub user: Address,
+    pub shares: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct MaxLockDurationUpdated {
+    pub duration: u64,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct GuardianUpdated {
+    pub guardian: Address,
+    pub enabled: bool,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct AddressBlocked {
+    pub account: Address,
+    pub guardian: Address,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct AddressUnblocked {
+    pub account: Address,
+    pub guardian: Address,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct PositionRecoveryQueued {
+    pub from: Address,
+    pub to: Address,
+    pub executable_at: u64,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct PositionRecoveryCancelled {
+    pub from: Address,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct PositionRecovered {
+    pub from: Address,
+    pub to: Address,
+    pub shares: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct KeeperUpdated {
+    pub keeper: Address,
+    pub enabled: bool,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct PoolDeallocated {
+    pub pool: String,
+    pub amount: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct DelegationSynced {
+    pub pool: String,
+    pub delegated: U512,
+    pub rewards: U512,
+    pub loss: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct UnbondingStarted {
+    pub pool: String,
+    pub amount: U512,
+    pub available_at: u64,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct UnbondedClaimed {
+    pub amount: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct UnbondingPeriodUpdated {
+    pub period: u64,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct ValidatorTargetUpdated {
+    pub pool: String,
+    pub weight: u32,
+    pub min_delegation: U512,
+    pub max_delegation: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct DelegationsRebalanced {
+    pub delegated: U512,
+    pub unbonded: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct PpsCheckpointIntervalUpdated {
+    pub interval: u64,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct OracleUpdated {
+    pub oracle: Address,
+    pub enabled: bool,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct ApyGuardsUpdated {
+    pub max_apy_change: u32,
+    pub max_apy_age: u64,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct VaultFunded {
+    pub from: Address,
+    pub amount: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct WithdrawalRequested {
+    pub request_id: u64,
+    pub user: Address,
+    pub shares: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct WithdrawalProcessed {
+    pub request_id: u64,
+    pub user: Address,
+    pub shares: U512,
+    pub amount: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+#[odra::event]
+pub struct WithdrawalClaimed {
+    pub request_id: u64,
+    pub user: Address,
+    pub amount: U512,
+    pub timestamp: u64,
+    pub nonce: u64,
+}
+
+/// User position information
+#[odra::odra_type]
+pub struct UserPosition {
+    pub shares: U512,
+    pub deposited_amount: U512,
+    pub last_deposit_time: u64,
+    pub total_rewards: U512,
+}
+
+impl Default for UserPosition {
+    fn default() -> Self {
+        Self {
+            shares: U512::zero(),
+            deposited_amount: U512::zero(),
+            last_deposit_time: 0,
+            total_rewards: U512::zero(),
+        }
+    }
+}
+
+/// Pool information
+#[odra::odra_type]
+pub struct PoolInfo {
+    pub name: String,
+    pub total_allocated: U512,
+    pub current_apy: u32, // APY in basis points (e.g., 1250 = 12.50%)
+    pub risk_level: u8,   // 1-5 (1 = lowest risk)
+    pub validator: Option<PublicKey>, // Set for native delegation pools
+    pub apy_updated_at: u64,
+}
+
+/// Strategy target of a delegation pool
+#[odra::odra_type]
+pub struct ValidatorTarget {
+    pub weight: u32, // Basis points, scaled up if strategy weights sum below 100%
+    pub min_delegation: U512, // Validators whose target falls below this get nothing
+    pub max_delegation: U512, // Zero means no cap
+}
+
+/// Target against actual weight of a delegation pool
+#[odra::odra_type]
+pub struct ValidatorWeight {
+    pub pool: String,
+    pub delegated: U512,
+    pub target_weight: u32,
+    pub actual_weight: u32,
+}
+
+/// Price per share at a point in time
+#[odra::odra_type]
+pub struct PpsCheckpoint {
+    pub timestamp: u64,
+    pub price_per_share: U512,
+}
+
+/// Pool APY declared at a point in time
+#[odra::odra_type]
+pub struct ApyCheckpoint {
+    pub timestamp: u64,
+    pub apy: u32,
+}
+
+/// Undelegated stake in transit back to the vault purse
+#[odra::odra_type]
+pub struct UnbondingEntry {
+    pub pool: String,
+    pub amount: U512,
+    pub available_at: u64,
+}
+
+/// Queued withdrawal, priced when a keeper processes it
+#[odra::odra_type]
+pub struct WithdrawalRequest {
+    pub id: u64,
+    pub user: Address,
+    pub shares: U512,
+    pub amount: U512, // Zero until processed
+    pub requested_at: u64,
+    pub processed: bool,
+    pub claimed: bool,
+}
+
+/// Shares a user has put on cooldown, withdrawable within [withdrawable_from, withdrawable_until]
+#[odra::odra_type]
+pub struct Cooldown {
+    pub shares: U512,
+    pub started_at: u64,
+    pub withdrawable_from: u64,
+    pub withdrawable_until: u64,
+}
+
+/// Message a user signs off-chain to authorize an action through a relayer
+#[odra::odra_type]
+pub struct PermitMessage {
+    pub action: String, // "withdraw" or "set_operator"
+    pub owner: Address,
+    pub target: Address, // Withdrawal recipient or operator
+    pub amount: U512,    // Shares to withdraw, 1 or 0 to approve or revoke an operator
+    pub nonce: u64,
+    pub deadline: u64,
+    pub contract: Address,
+}
+
+/// Pool definition for `add_pools`
+#[odra::odra_type]
+pub struct NewPool {
+    pub name: String,
+    pub initial_apy: u32,
+    pub risk_level: u8,
+}
+
+/// APY update for `update_apys`
+#[odra::odra_type]
+pub struct ApyUpdate {
+    pub pool: String,
+    pub apy: u32,
+}
+
+/// Allocation for `allocate_many`
+#[odra::odra_type]
+pub struct PoolAllocation {
+    pub pool: String,
+    pub amount: U512,
+}
+
+/// Cumulative results of a referrer
+#[odra::odra_type]
+#[derive(Default)]
+pub struct ReferralStats {
+    pub referred_users: u32,
+    pub referred_deposits: U512,
+    pub earnings: U512, // Value of the fee shares credited to the referrer
+}
+
+/// A reward token and the reward per share at which it took over
+#[odra::odra_type]
+pub struct RewardTokenEpoch {
+    pub token: Address,
+    pub start_reward_per_share: U512,
+}
+
+/// Reward stream parameters from `time` until the next reward update
+#[odra::odra_type]
+pub struct RewardSample {
+    pub time: u64,
+    pub reward_per_share: U512,
+    pub rate: U512,
+    pub period_finish: u64,
+    pub staked: U512,
+}
+
+/// Shares locked until unlock_at in exchange for a reward boost
+#[odra::odra_type]
+pub struct ShareLock {
+    pub shares: U512,
+    pub unlock_at: u64,
+    pub boost: u32, // Extra reward weight in basis points, scales with lock duration
+}
+
+/// Queued move of a blocked address' position to a new address
+#[odra::odra_type]
+pub struct PendingRecovery {
+    pub to: Address,
+    pub executable_at: u64,
+}
+
+/// Deposit limits, a zero cap means unlimited
+#[odra::odra_type]
+pub struct VaultLimits {
+    pub max_tvl: U512,
+    pub max_deposit_per_user: U512, // Cap on a user's deposited principal
+    pub min_deposit: U512,
+}
+
+/// Pause flags per operation, plus emergency mode
+#[odra::odra_type]
+#[derive(Default)]
+pub struct PauseState {
+    pub deposits_paused: bool,
+    pub withdrawals_paused: bool,
+    pub allocations_paused: bool,
+    pub harvests_paused: bool,
+    pub emergency_mode: bool, // Deposits and allocations frozen, withdrawals always open
+    pub reason: String,
+    pub paused_at: u64,
+    pub extended_until: u64, // Set by a unanimous committee decision
+}
+
+impl PauseState {
+    fn any_flag_set(&self) -> bool {
+        self.deposits_paused
+            || self.withdrawals_paused
+            || self.allocations_paused
+            || self.harvests_paused
+    }
+
+    fn any_paused(&self) -> bool {
+        self.any_flag_set() || self.emergency_mode
+    }
+}
+
+/// Effective pause state, taking the maximum pause duration into account
+#[odra::odra_type]
+pub struct PauseStatus {
+    pub paused: bool,
+    pub expires_at: Option<u64>,
+}
+
+/// Operations that can be paused independently
+enum Operation {
+    Deposit,
+    Withdrawal,
+    Allocation,
+    Harvest,
+}
+
+/// Admin committee proposal: a privileged entry point call awaiting approvals
+#[odra::odra_type]
+pub struct Proposal {
+    pub id: u32,
+    pub proposer: Address,
+    pub entry_point: String,
+    pub args: Bytes, // Serialized RuntimeArgs of the call
+    pub approvals: u32,
+    pub executed: bool,
+    pub created_at: u64,
+}
+
+/// Shareholder proposal: a governable entry point call put to a share-weighted vote
+#[odra::odra_type]
+pub struct GovernanceProposal {
+    pub id: u32,
+    pub proposer: Address,
+    pub entry_point: String,
+    pub args: Bytes, // Serialized RuntimeArgs of the call
+    pub votes_for: U512,
+    pub votes_against: U512,
+    pub voting_ends_at: u64,
+    pub executed: bool,
+    pub created_at: u64,
+}
+
+/// Share balance from a block time onwards
+#[odra::odra_type]
+pub struct ShareCheckpoint {
+    pub time: u64,
+    pub shares: U512,
+}
+
+/// Main Yield Optimizer Contract
+#[odra::module(
+    events = [
+        Deposit,
+        Withdrawal,
+        Rebalance,
+        RewardsHarvested,
+        PoolAdded,
+        PoolApyUpdated,
+        PoolAllocated,
+        Paused,
+        Unpaused,
+        ManagementFeeUpdated,
+        ProposalCreated,
+        ProposalApproved,
+        ProposalExecuted,
+        GovernanceProposalCreated,
+        VoteCast,
+        GovernanceProposalExecuted,
+        GovernanceConfigUpdated,
+        SignerAdded,
+        SignerRemoved,
+        ThresholdChanged,
+        PauseStateChanged,
+        PauseExtended,
+        MaxPauseDurationUpdated,
+        EmergencyExit,
+        LimitsUpdated,
+        CooldownConfigUpdated,
+        CooldownStarted,
+        AllowlistUpdated,
+        AllowlistToggled,
+        SharesTransferred,
+        OperatorSet,
+        PermitUsed,
+        ReferralRecorded,
+        ManagementFeeCharged,
+        ReferralShareUpdated,
+        RewardTokenSet,
+        RewardPeriodStarted,
+        RewardsClaimed,
+        LockCreated,
+        LockExtended,
+        LockReleased,
+        MaxLockDurationUpdated,
+        GuardianUpdated,
+        AddressBlocked,
+        AddressUnblocked,
+        PositionRecoveryQueued,
+        PositionRecoveryCancelled,
+        PositionRecovered,
+        KeeperUpdated,
+        PoolDeallocated,
+        DelegationSynced,
+        UnbondingStarted,
+        UnbondedClaimed,
+        UnbondingPeriodUpdated,
+        ValidatorTargetUpdated,
+        DelegationsRebalanced,
+        VaultFunded,
+        PpsCheckpointIntervalUpdated,
+        OracleUpdated,
+        ApyGuardsUpdated,
+        WithdrawalRequested,
+        WithdrawalProcessed,
+        WithdrawalClaimed
+    ],
+    errors = Error
+)]
+pub struct YieldOptimizer {
+    /// Admin committee members
+    signers: Var<Vec<Address>>,
+    /// Approvals required to execute a proposal (M of N)
+    threshold: Var<u32>,
+    /// Committee proposals by id
+    proposals: Mapping<u32, Proposal>,
+    /// Number of proposals created
+    proposal_count: Var<u32>,
+    /// Signer approvals per proposal
+    proposal_approvals: Mapping<(u32, Address), bool>,
+    /// Shareholder proposals by id, voting power is read just before `created_at`
+    governance_proposals: Mapping<u32, GovernanceProposal>,
+    /// Number of shareholder proposals created
+    governance_proposal_count: Var<u32>,
+    /// Shareholders that voted per proposal
+    governance_votes: Mapping<(u32, Address), bool>,
+    /// Length of a vote in milliseconds
+    voting_period: Var<u64>,
+    /// Share of the snapshot supply that must vote for a proposal, in basis points
+    quorum_bp: Var<u32>,
+    /// Share balance history per user, keyed by (user, index)
+    share_checkpoints: Mapping<(Address, u32), ShareCheckpoint>,
+    /// Number of share checkpoints per user
+    share_checkpoint_count: Mapping<Address, u32>,
+    /// History of shares able to vote (total minus queued), by index
+    supply_checkpoints: Mapping<u32, ShareCheckpoint>,
+    /// Number of supply checkpoints
+    supply_checkpoint_count: Var<u32>,
+    /// Total value locked in the optimizer
+    total_tvl: Var<U512>,
+    /// Total shares issued
+    total_shares: Var<U512>,
+    /// Mapping of user addresses to their positions
+    user_positions: Mapping<Address, UserPosition>,
+    /// Available liquidity pools
+    pools: Mapping<String, PoolInfo>,
+    /// Names of all pools, in creation order
+    pool_names: Var<Vec<String>>,
+    /// Sum of all pool allocations
+    total_allocated: Var<U512>,
+    /// Management fee (in basis points)
+    management_fee: Var<u32>,
+    /// Cap on total value locked (0 = unlimited)
+    max_tvl: Var<U512>,
+    /// Cap on each user's deposited principal (0 = unlimited)
+    max_deposit_per_user: Var<U512>,
+    /// Smallest accepted deposit
+    min_deposit: Var<U512>,
+    /// Only allowlisted addresses may deposit when enabled
+    allowlist_enabled: Var<bool>,
+    /// Addresses approved for deposits
+    allowlist: Mapping<Address, bool>,
+    /// Addresses allowed to block and unblock accounts
+    guardians: Mapping<Address, bool>,
+    /// Frozen addresses
+    blocklist: Mapping<Address, bool>,
+    /// Timelocked recoveries of blocked positions, by blocked address
+    pending_recoveries: Mapping<Address, Option<PendingRecovery>>,
+    /// Operators approved by each position owner, keyed by (owner, operator)
+    operators: Mapping<(Address, Address), bool>,
+    /// Next permit nonce per owner
+    permit_nonces: Mapping<Address, u64>,
+    /// Referrer of each referred user
+    referrers: Mapping<Address, Option<Address>>,
+    /// Last time the management fee was charged on each position
+    fee_accrued_at: Mapping<Address, u64>,
+    /// Cumulative results per referrer
+    referral_stats: Mapping<Address, ReferralStats>,
+    /// Share of referred users' management fees paid to referrers, in basis points
+    referral_share: Var<u32>,
+    /// Shares locked in the withdrawal queue, excluded from reward streaming
+    queued_shares: Var<U512>,
+    /// CEP-18 token streamed to shareholders
+    reward_token: Var<Address>,
+    /// Reward tokens streamed per millisecond
+    reward_rate: Var<U512>,
+    /// End of the current reward period
+    reward_period_finish: Var<u64>,
+    /// Last time reward_per_share_stored was brought up to date
+    reward_last_update: Var<u64>,
+    /// Rewards accumulated per share, scaled by REWARD_PRECISION
+    reward_per_share_stored: Var<U512>,
+    /// Accumulated reward per share already credited to each user
+    user_reward_per_share_paid: Mapping<Address, U512>,
+    /// Closed reward update intervals, used to find the reward per share at lock expiry
+    reward_samples: Mapping<u64, RewardSample>,
+    /// Number of reward samples
+    reward_sample_count: Var<u64>,
+    /// Every reward token in order, so rewards earned under an old token stay in it
+    reward_token_epochs: Var<Vec<RewardTokenEpoch>>,
+    /// Credited but unclaimed rewards, keyed by (user, token)
+    user_reward_balance: Mapping<(Address, Address), U512>,
+    /// Rewards streamed to holders but not claimed yet, per token
+    reward_owed: Mapping<Address, U512>,
+    /// Active share lock per user
+    share_locks: Mapping<Address, Option<ShareLock>>,
+    /// Extra reward weight of all unexpired locks, in shares
+    total_lock_boost: Var<U512>,
+    /// Extra reward weight leaving the total at each lock expiry time
+    lock_expiries: Mapping<u64, U512>,
+    /// Pending lock expiry times in ascending order
+    lock_expiry_times: Var<Vec<u64>>,
+    /// Longest allowed lock in milliseconds, earning MAX_LOCK_BOOST
+    max_lock_duration: Var<u64>,
+    /// Addresses allowed to run maintenance operations
+    keepers: Mapping<Address, bool>,
+    /// Withdrawal requests by id
+    withdrawal_requests: Mapping<u64, WithdrawalRequest>,
+    /// Ids of each user's withdrawal requests
+    user_withdrawal_requests: Mapping<Address, Vec<u64>>,
+    /// Id of the next withdrawal request
+    next_withdrawal_id: Var<u64>,
+    /// Id of the oldest unprocessed withdrawal request
+    withdrawal_queue_head: Var<u64>,
+    /// Processed withdrawals awaiting their claim
+    reserved_for_withdrawals: Var<U512>,
+    /// Sequence number of the next emitted event
+    event_nonce: Var<u64>,
+    /// Addresses allowed to push APY updates
+    oracles: Mapping<Address, bool>,
+    /// Largest APY move per update in basis points, 0 means unlimited
+    max_apy_change: Var<u32>,
+    /// Age in milliseconds after which a pool's APY is stale, 0 disables staleness
+    max_apy_age: Var<u64>,
+    /// Ring buffer of price-per-share checkpoints, slot = index % PPS_HISTORY_SIZE
+    pps_checkpoints: Mapping<u64, PpsCheckpoint>,
+    /// Number of checkpoints ever written
+    pps_checkpoint_count: Var<u64>,
+    /// Minimum time between checkpoints in milliseconds
+    pps_checkpoint_interval: Var<u64>,
+    /// Ring buffer of APY checkpoints per pool, slot = index % APY_HISTORY_SIZE
+    pool_apy_history: Mapping<(String, u64), ApyCheckpoint>,
+    /// Number of APY checkpoints ever written per pool
+    pool_apy_history_count: Mapping<String, u64>,
+    /// Cooldown before shares can be withdrawn in milliseconds, 0 disables it
+    cooldown_period: Var<u64>,
+    /// How long cooled-down shares stay withdrawable in milliseconds
+    withdrawal_window: Var<u64>,
+    /// Active cooldown per user
+    cooldowns: Mapping<Address, Option<Cooldown>>,
+    /// Unbonding stake per validator
+    unbonding: Mapping<PublicKey, Vec<UnbondingEntry>>,
+    /// Validators with unbonding stake
+    unbonding_validators: Var<Vec<PublicKey>>,
+    /// Sum of all unbonding stake
+    total_unbonding: Var<U512>,
+    /// Expected time between undelegating and funds returning, in milliseconds
+    unbonding_period: Var<u64>,
+    /// Target weights of delegation pools in the staking strategy
+    validator_targets: Mapping<String, Option<ValidatorTarget>>,
+    /// Delegation pools with a target weight
+    strategy_pools: Var<Vec<String>>,
+    /// Pause flags and emergency mode
+    pause_state: Var<PauseState>,
+    /// Set once the vault has been unwound by an emergency exit
+    exited: Var<bool>,
+    /// Time after which pause flags stop applying (0 = no limit)
+    max_pause_duration: Var<u64>,
+    /// Time the last pause was lifted by an unpause
+    pause_ended_at: Var<u64>,
+}
+
+#[odra::module]
+impl YieldOptimizer {
+    /// Initialize the contract, the deployer becomes a 1-of-1 committee
+    pub fn init(&mut self, management_fee_bp: u32) {
+        let caller = self.env().caller();
+        self.signers.set(vec![caller]);
+        self.threshold.set(1);
+        self.proposal_count.set(0);
+        self.total_tvl.set(U512::zero());
+        self.total_shares.set(U512::zero());
+        self.total_allocated.set(U512::zero());
+        self.management_fee.set(management_fee_bp);
+        self.max_tvl.set(U512::zero());
+        self.max_deposit_per_user.set(U512::zero());
+        self.min_deposit.set(U512::zero());
+        self.allowlist_enabled.set(false);
+        self.next_withdrawal_id.set(0);
+        self.withdrawal_queue_head.set(0);
+        self.reserved_for_withdrawals.set(U512::zero());
+        self.event_nonce.set(0);
+        self.max_apy_change.set(0);
+        self.max_apy_age.set(0);
+        self.referral_share.set(0);
+        self.queued_shares.set(U512::zero());
+        self.reward_rate.set(U512::zero());
+        self.reward_period_finish.set(0);
+        self.reward_last_update.set(0);
+        self.reward_per_share_stored.set(U512::zero());
+        self.total_lock_boost.set(U512::zero());
+        self.max_lock_duration.set(YEAR_MS);
+        self.voting_period.set(DEFAULT_VOTING_PERIOD);
+        self.quorum_bp.set(DEFAULT_QUORUM_BP);
+        self.cooldown_period.set(0);
+        self.withdrawal_window.set(0);
+        self.total_unbonding.set(U512::zero());
+        self.unbonding_period.set(DEFAULT_UNBONDING_PERIOD);
+        self.pause_state.set(PauseState::default());
+        self.max_pause_duration.set(0);
+        self.exited.set(false);
+        self.pps_checkpoint_count.set(0);
+        self.pps_checkpoint_interval.set(0);
+        self.record_pps_checkpoint();
+    }
+
+    /// Deposit funds into the yield optimizer
+    pub fn deposit(&mut self, amount: U512) {
+        let caller = self.env().caller();
+        self.deposit_internal(caller, caller, amount);
+    }
+
+    /// Deposit funds, recording the referrer on the caller's first deposit
+    pub fn deposit_with_referrer(&mut self, amount: U512, referrer: Address) {
+        let caller = self.env().caller();
+        if referrer == caller {
+            self.env().revert(Error::SelfReferral)
+        }
+
+        let is_first_deposit = self.user_positions.get(&caller).is_none();
+        if is_first_deposit && self.get_referrer(caller).is_none() {
+            self.referrers.set(&caller, Some(referrer));
+
+            let mut stats = self.get_referral_stats(referrer);
+            stats.referred_users += 1;
+            self.referral_stats.set(&referrer, stats);
+
+            self.env().emit_event(ReferralRecorded {
+                user: caller,
+                referrer,
+                timestamp: self.env().get_block_time(),
+                nonce: self.next_event_nonce(),
+            });
+        }
+
+        self.deposit_internal(caller, caller, amount);
+    }
+
+    /// Deposit funds credited to another address
+    pub fn deposit_for(&mut self, beneficiary: Address, amount: U512) {
+        let caller = self.env().caller();
+        self.deposit_internal(caller, beneficiary, amount);
+    }
+
+    /// Withdraw funds from the yield optimizer
+    pub fn withdraw(&mut self, shares_to_withdraw: U512) {
+        let caller = self.env().caller();
+        self.withdraw_internal(caller, caller, shares_to_withdraw, caller);
+    }
+
+    /// Withdraw funds with the proceeds going to another address
+    pub fn withdraw_to(&mut self, shares_to_withdraw: U512, recipient: Address) {
+        let caller = self.env().caller();
+        self.withdraw_internal(caller, caller, shares_to_withdraw, recipient);
+    }
+
+    /// Withdraw from an owner's position (approved operator only)
+    pub fn withdraw_from(&mut self, owner: Address, shares_to_withdraw: U512, recipient: Address) {
+        let caller = self.env().caller();
+        if caller != owner && !self.is_operator(owner, caller) {
+            self.env().revert(Error::NotOperator)
+        }
+        self.withdraw_internal(caller, owner, shares_to_withdraw, recipient);
+    }
+
+    /// Approve or revoke an operator for the caller's position
+    pub fn set_operator(&mut self, operator: Address, approved: bool) {
+        let owner = self.env().caller();
+        self.set_operator_internal(owner, operator, approved);
+    }
+
+    /// Check if an operator is approved for an owner's position
+    pub fn is_operator(&self, owner: Address, operator: Address) -> bool {
+        self.operators.get(&(owner, operator)).unwrap_or(false)
+    }
+
+    // ========== Referrals ==========
+
+    /// Set the share of referred users' management fees paid to referrers (owner only)
+    pub fn set_referral_share(&mut self, share_bp: u32) {
+        self.require_owner();
+        if share_bp > 10000 {
+            self.env().revert(Error::InvalidReferralShare)
+        }
+        self.referral_share.set(share_bp);
+
+        self.env().emit_event(ReferralShareUpdated {
+            share_bp,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Get the share of referred users' management fees paid to referrers
+    pub fn get_referral_share(&self) -> u32 {
+        self.referral_share.get().unwrap_or(0)
+    }
+
+    /// Get the referrer of a user
+    pub fn get_referrer(&self, user: Address) -> Option<Address> {
+        self.referrers.get(&user).flatten()
+    }
+
+    /// Get cumulative referral results of a referrer
+    pub fn get_referral_stats(&self, referrer: Address) -> ReferralStats {
+        self.referral_stats.get(&referrer).unwrap_or_default()
+    }
+
+    // ========== Reward Streaming ==========
+
+    /// Set the CEP-18 token streamed to shareholders (owner only)
+    pub fn set_reward_token(&mut self, token: Address) {
+        self.require_owner();
+        if self.env().get_block_time() < self.get_reward_period_finish() {
+            self.env().revert(Error::RewardPeriodActive)
+        }
+        self.update_reward(None);
+        self.reward_token.set(token);
+
+        // Rewards streamed so far stay claimable in the previous token
+        let mut epochs = self.get_reward_token_epochs();
+        epochs.push(RewardTokenEpoch {
+            token,
+            start_reward_per_share: self.reward_per_share(),
+        });
+        self.reward_token_epochs.set(epochs);
+
+        self.env().emit_event(RewardTokenSet {
+            token,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Stream reward tokens already sent to the vault over duration milliseconds (owner only)
+    pub fn notify_reward_amount(&mut self, reward: U512, duration: u64) {
+        self.require_owner();
+        self.require_positive_amount(reward);
+        if duration == 0 {
+            self.env().revert(Error::InvalidRewardPeriod)
+        }
+        let token = match self.reward_token.get() {
+            Some(t) => t,
+            None => self.env().revert(Error::RewardTokenNotSet),
+        };
+
+        self.update_reward(None);
+
+        // Leftovers of a running period roll into the new one
+        let now = self.env().get_block_time();
+        let period_finish = self.get_reward_period_finish();
+        let mut total = reward;
+        if now < period_finish {
+            total += self.get_reward_rate() * U512::from(period_finish - now);
+        }
+        let reward_rate = total / U512::from(duration);
+
+        // Rewards already streamed to holders can't fund a new period
+        let balance = to_u512(
+            Cep18TokenContractRef::new(self.env(), token).balance_of(&self.env().self_address()),
+        );
+        let available = balance.saturating_sub(self.reward_owed.get(&token).unwrap_or_default());
+        if reward_rate > available / U512::from(duration) {
+            self.env().revert(Error::InsufficientRewardBalance)
+        }
+
+        self.reward_rate.set(reward_rate);
+        self.reward_last_update.set(now);
+        self.reward_period_finish.set(now + duration);
+
+        self.env().emit_event(RewardPeriodStarted {
+            reward,
+            reward_rate,
+            period_finish: now + duration,
+            timestamp: now,
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Claim streamed rewards in every reward token, leaving the position untouched
+    pub fn claim_rewards(&mut self) {
+        let caller = self.env().caller();
+        self.require_not_blocked(caller);
+        self.update_reward(Some(caller));
+
+        let mut tokens: Vec<Address> = Vec::new();
+        for epoch in self.get_reward_token_epochs() {
+            if !tokens.contains(&epoch.token) {
+                tokens.push(epoch.token);
+            }
+        }
+
+        for token in tokens {
+            let amount = self
+                .user_reward_balance
+                .get(&(caller, token))
+                .unwrap_or(U512::zero());
+            if amount == U512::zero() {
+                continue;
+            }
+
+            self.user_reward_balance.set(&(caller, token), U512::zero());
+            let owed = self.reward_owed.get(&token).unwrap_or_default();
+            self.reward_owed.set(&token, owed.saturating_sub(amount));
+            let amount_u256 = match to_u256(amount) {
+                Some(a) => a,
+                None => self.env().revert(Error::AmountOverflow),
+            };
+            Cep18TokenContractRef::new(self.env(), token).transfer(&caller, &amount_u256);
+
+            self.env().emit_event(RewardsClaimed {
+                user: caller,
+                token,
+                amount,
+                timestamp: self.env().get_block_time(),
+                nonce: self.next_event_nonce(),
+            });
+        }
+    }
+
+    /// Get reward tokens a user can claim
+    pub fn pending_rewards(&self, user: Address) -> U512 {
+        match self.reward_token.get() {
+            Some(token) => self.get_claimable_rewards(user, token),
+            None => U512::zero(),
+        }
+    }
+
+    /// Get a user's unclaimed rewards in a current or former reward token
+    pub fn get_claimable_rewards(&self, user: Address, token: Address) -> U512 {
+        let credited = self
+            .user_reward_balance
+            .get(&(user, token))
+            .unwrap_or(U512::zero());
+        self.unsettled_rewards(user, self.reward_per_share())
+            .into_iter()
+            .filter(|(t, _)| *t == token)
+            .fold(credited, |total, (_, amount)| total + amount)
+    }
+
+    /// Get rewards accumulated per share, scaled by 10^18
+    pub fn reward_per_share(&self) -> U512 {
+        self.reward_per_share_until(self.env().get_block_time())
+    }
+
+    /// Get the reward token
+    pub fn get_reward_token(&self) -> Option<Address> {
+        self.reward_token.get()
+    }
+
+    /// Get every reward token with the reward per share at which it took over
+    pub fn get_reward_token_epochs(&self) -> Vec<RewardTokenEpoch> {
+        self.reward_token_epochs.get().unwrap_or_default()
+    }
+
+    /// Get reward tokens streamed per millisecond
+    pub fn get_reward_rate(&self) -> U512 {
+        self.reward_rate.get().unwrap_or(U512::zero())
+    }
+
+    /// Get the end of the current reward period
+    pub fn get_reward_period_finish(&self) -> u64 {
+        self.reward_period_finish.get().unwrap_or(0)
+    }
+
+    // ========== Share Locks ==========
+
+    /// Lock shares for duration milliseconds to boost their reward weight
+    pub fn lock(&mut self, shares: U512, duration: u64) {
+        self.require_positive_amount(shares);
+        let caller = self.env().caller();
+        self.require_not_blocked(caller);
+
+        // Settling first releases an expired lock
+        self.update_reward(Some(caller));
+        if self.get_lock(caller).is_some() {
+            self.env().revert(Error::LockExists)
+        }
+        if self.get_user_position(&caller).shares < shares {
+            self.env().revert(Error::InsufficientShares)
+        }
+
+        let boost = self.lock_boost(duration);
+        let unlock_at = self.env().get_block_time() + duration;
+        self.set_lock(
+            caller,
+            Some(ShareLock {
+                shares,
+                unlock_at,
+                boost,
+            }),
+        );
+
+        self.env().emit_event(LockCreated {
+            user: caller,
+            shares,
+            unlock_at,
+            boost,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Relock the caller's shares for duration milliseconds from now, ending later than before
+    pub fn extend_lock(&mut self, duration: u64) {
+        let caller = self.env().caller();
+        let mut lock = match self.get_lock(caller) {
+            Some(l) => l,
+            None => self.env().revert(Error::NoLock),
+        };
+
+        let unlock_at = self.env().get_block_time() + duration;
+        if unlock_at <= lock.unlock_at {
+            self.env().revert(Error::InvalidLockDuration)
+        }
+        lock.boost = self.lock_boost(duration);
+        lock.unlock_at = unlock_at;
+
+        self.update_reward(Some(caller));
+        self.set_lock(caller, Some(lock.clone()));
+
+        self.env().emit_event(LockExtended {
+            user: caller,
+            unlock_at,
+            boost: lock.boost,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Release an expired lock, ending its boost (anyone can release for the user)
+    pub fn unlock(&mut self, user: Address) {
+        let lock = match self.get_lock(user) {
+            Some(l) => l,
+            None => self.env().revert(Error::NoLock),
+        };
+        if self.env().get_block_time() < lock.unlock_at {
+            self.env().revert(Error::LockNotExpired)
+        }
+        self.release_lock(user);
+    }
+
+    /// Set the longest allowed lock duration (owner only)
+    pub fn set_max_lock_duration(&mut self, duration: u64) {
+        self.require_owner();
+        if duration == 0 {
+            self.env().revert(Error::InvalidLockDuration)
+        }
+        self.max_lock_duration.set(duration);
+
+        self.env().emit_event(MaxLockDurationUpdated {
+            duration,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Get the longest allowed lock duration
+    pub fn get_max_lock_duration(&self) -> u64 {
+        self.max_lock_duration.get().unwrap_or(YEAR_MS)
+    }
+
+    /// Get a user's share lock
+    pub fn get_lock(&self, user: Address) -> Option<ShareLock> {
+        self.share_locks.get(&user).flatten()
+    }
+
+    /// Get a user's reward weight: shares plus the boost on locked shares
+    pub fn get_boosted_balance(&self, user: Address) -> U512 {
+        let shares = self.get_user_position(&user).shares;
+        match self.get_lock(user) {
+            Some(lock) if self.env().get_block_time() < lock.unlock_at => {
+                shares + lock_extra(&lock)
+            }
+            _ => shares,
+        }
+    }
+
+    // ========== Permits ==========
+
+    /// Withdraw on behalf of the signer of a "withdraw" permit
+    pub fn withdraw_with_permit(
+        &mut self,
+        owner_key: PublicKey,
+        shares_to_withdraw: U512,
+        recipient: Address,
+        deadline: u64,
+        signature: Bytes,
+    ) {
+        let owner = self.use_permit(
+            owner_key,
+            "withdraw",
+            recipient,
+            shares_to_withdraw,
+            deadline,
+            signature,
+        );
+        let caller = self.env().caller();
+        self.withdraw_internal(caller, owner, shares_to_withdraw, recipient);
+    }
+
+    /// Approve or revoke an operator on behalf of the signer of a "set_operator" permit
+    pub fn set_operator_with_permit(
+        &mut self,
+        owner_key: PublicKey,
+        operator: Address,
+        approved: bool,
+        deadline: u64,
+        signature: Bytes,
+    ) {
+        let amount = if approved { U512::one() } else { U512::zero() };
+        let owner = self.use_permit(
+            owner_key,
+            "set_operator",
+            operator,
+            amount,
+            deadline,
+            signature,
+        );
+        self.set_operator_internal(owner, operator, approved);
+    }
+
+    /// Get the nonce the owner's next permit must carry
+    pub fn get_permit_nonce(&self, owner: Address) -> u64 {
+        self.permit_nonces.get(&owner).unwrap_or(0)
+    }
+
+    /// Get user position details
+    pub fn get_position(&self, user: Address) -> UserPosition {
+        self.get_user_position(&user)
+    }
+
+    /// Get total value locked
+    pub fn get_tvl(&self) -> U512 {
+        self.total_tvl.get().unwrap_or(U512::zero())
+    }
+
+    /// Get total shares issued
+    pub fn get_total_shares(&self) -> U512 {
+        self.total_shares.get().unwrap_or(U512::zero())
+    }
+
+    /// Get user's current value (including rewards)
+    pub fn get_user_value(&self, user: Address) -> U512 {
+        let position = self.get_user_position(&user);
+        if position.shares == U512::zero() {
+            return U512::zero();
+        }
+
+        self.calculate_withdrawal_amount(position.shares)
+    }
+
+    /// Add a new liquidity pool (owner only)
+    pub fn add_pool(&mut self, name: String, initial_apy: u32, risk_level: u8) {
+        self.require_owner();
+
+        // Replacing a pool would drop its allocation from the books
+        if self.pools.get(&name).is_some() {
+            self.env().revert(Error::PoolAlreadyExists)
+        }
+
+        let pool = PoolInfo {
+            name: name.clone(),
+            total_allocated: U512::zero(),
+            current_apy: initial_apy,
+            risk_level,
+            validator: None,
+            apy_updated_at: self.env().get_block_time(),
+        };
+
+        let mut names = self.get_pool_names();
+        names.push(name.clone());
+        self.pool_names.set(names);
+        self.pools.set(&name, pool);
+        self.record_apy_checkpoint(&name, initial_apy);
+
+        self.env().emit_event(PoolAdded {
+            pool: name,
+            initial_apy,
+            risk_level,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Update pool APY (owner or oracle)
+    pub fn update_pool_apy(&mut self, pool_name: String, new_apy: u32) {
+        if !self.is_oracle(self.env().caller()) {
+            self.require_owner();
+        }
+
+        if let Some(mut pool) = self.pools.get(&pool_name) {
+            let old_apy = pool.current_apy;
+            let max_change = self.max_apy_change.get().unwrap_or(0);
+            if max_change > 0 && old_apy.abs_diff(new_apy) > max_change {
+                self.env().revert(Error::ApyChangeTooLarge)
+            }
+
+            pool.current_apy = new_apy;
+            pool.apy_updated_at = self.env().get_block_time();
+            self.pools.set(&pool_name, pool);
+            self.record_apy_checkpoint(&pool_name, new_apy);
+
+            self.env().emit_event(PoolApyUpdated {
+                pool: pool_name,
+                old_apy,
+                new_apy,
+                timestamp: self.env().get_block_time(),
+                nonce: self.next_event_nonce(),
+            });
+        } else {
+            self.env().revert(Error::PoolNotFound)
+        }
+    }
+
+    /// Get pool information
+    pub fn get_pool_info(&self, pool_name: String) -> Option<PoolInfo> {
+        self.pools.get(&pool_name)
+    }
+
+    /// Allocate funds to a pool (owner only)
+    pub fn allocate_to_pool(&mut self, pool_name: String, amount: U512) {
+        self.require_owner();
+        self.require_not_paused(Operation::Allocation);
+        self.require_positive_amount(amount);
+
+        if let Some(mut pool) = self.pools.get(&pool_name) {
+            // Verify sufficient unallocated TVL
+            if self.get_idle_liquidity() < amount {
+                self.env().revert(Error::InsufficientTvl)
+            }
+
+            if let Some(validator) = pool.validator.clone() {
+                if self.env().self_balance() < amount {
+                    self.env().revert(Error::InsufficientBalance)
+                }
+                self.env().delegate(validator, amount);
+            }
+
+            pool.total_allocated += amount;
+            self.pools.set(&pool_name, pool);
+
+            let total_allocated = self.get_total_allocated();
+            self.total_allocated.set(total_allocated + amount);
+
+            self.env().emit_event(PoolAllocated {
+                pool: pool_name,
+                amount,
+                timestamp: self.env().get_block_time(),
+                nonce: self.next_event_nonce(),
+            });
+        } else {
+            self.env().revert(Error::PoolNotFound)
+        }
+    }
+
+    /// Rebalance between pools (owner only)
+    pub fn rebalance_pools(&mut self, from_pool: String, to_pool: String, amount: U512) {
+        self.require_owner();
+        self.require_not_paused(Operation::Allocation);
+        self.require_positive_amount(amount);
+
+        // Get source pool
+        let mut from = match self.pools.get(&from_pool) {
+            Some(p) => p,
+            None => self.env().revert(Error::PoolNotFound),
+        };
+
+        // Get destination pool
+        let mut to = match self.pools.get(&to_pool) {
+            Some(p) => p,
+            None => self.env().revert(Error::PoolNotFound),
+        };
+
+        // Delegated stake only moves through deallocate and allocate
+        if from.validator.is_some() || to.validator.is_some() {
+            self.env().revert(Error::NotDelegationPool)
+        }
+
+        // Verify sufficient allocation in source pool
+        if from.total_allocated < amount {
+            self.env().revert(Error::InsufficientAllocation)
+        }
+
+        // Update allocations
+        from.total_allocated -= amount;
+        to.total_allocated += amount;
+
+        self.pools.set(&from_pool, from);
+        self.pools.set(&to_pool, to);
+
+        // Emit event
+        self.env().emit_event(Rebalance {
+            from_pool,
+            to_pool,
+            amount,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Harvest rewards from a pool (owner only)
+    pub fn harvest_rewards(&mut self, pool_name: String, amount: U512) {
+        self.require_owner();
+        self.require_not_paused(Operation::Harvest);
+        self.require_positive_amount(amount);
+
+        // Verify pool exists, delegation rewards are measured by `sync_delegation`
+        match self.pools.get(&pool_name) {
+            None => self.env().revert(Error::PoolNotFound),
+            Some(pool) if pool.validator.is_some() => {
+                self.env().revert(Error::DelegationRewardsNotDeclarable)
+            }
+            Some(_) => {}
+        }
+
+        // Add rewards to TVL
+        let current_tvl = self.total_tvl.get().unwrap_or(U512::zero());
+        self.total_tvl.set(current_tvl + amount);
+        self.record_pps_checkpoint();
+
+        // Emit event
+        self.env().emit_event(RewardsHarvested {
+            pool: pool_name,
+            amount,
+            price_per_share: self.get_price_per_share(),
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Get value of one share in motes, scaled by PRICE_PRECISION
+    pub fn get_price_per_share(&self) -> U512 {
+        let total_shares = self.get_total_shares();
+        if total_shares == U512::zero() {
+            return U512::from(PRICE_PRECISION);
+        }
+        self.get_tvl() * U512::from(PRICE_PRECISION) / total_shares
+    }
+
+    /// Set the minimum time between price-per-share checkpoints (owner only)
+    pub fn set_pps_checkpoint_interval(&mut self, interval: u64) {
+        self.require_owner();
+        self.pps_checkpoint_interval.set(interval);
+
+        self.env().emit_event(PpsCheckpointIntervalUpdated {
+            interval,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Get the minimum time between price-per-share checkpoints
+    pub fn get_pps_checkpoint_interval(&self) -> u64 {
+        self.pps_checkpoint_interval.get().unwrap_or(0)
+    }
+
+    /// Get the latest n price-per-share checkpoints, oldest first
+    pub fn get_pps_history(&self, n: u32) -> Vec<PpsCheckpoint> {
+        let count = self.pps_checkpoint_count.get().unwrap_or(0);
+        let start = count.saturating_sub((n as u64).min(PPS_HISTORY_SIZE));
+        (start..count)
+            .filter_map(|i| self.pps_checkpoints.get(&(i % PPS_HISTORY_SIZE)))
+            .collect()
+    }
+
+    /// Get the annualized return in basis points measured over the last window_seconds
+    pub fn get_realized_apy(&self, window_seconds: u64) -> u32 {
+        let now = self.env().get_block_time();
+        let since = now.saturating_sub(window_seconds.saturating_mul(1000));
+
+        // Measure from the oldest checkpoint inside the window to the current price
+        let start = match self
+            .get_pps_history(PPS_HISTORY_SIZE as u32)
+            .into_iter()
+            .find(|checkpoint| checkpoint.timestamp >= since)
+        {
+            Some(c) => c,
+            None => return 0,
+        };
+        let elapsed = now - start.timestamp;
+        let current = self.get_price_per_share();
+        if elapsed == 0 || current <= start.price_per_share {
+            return 0;
+        }
+
+        let apy = (current - start.price_per_share) * U512::from(10000u32) * U512::from(YEAR_MS)
+            / (start.price_per_share * U512::from(elapsed));
+        apy.min(U512::from(u32::MAX)).as_u32()
+    }
+
+    /// Get the nonce the next event will carry, i.e. the number of events emitted
+    pub fn get_event_nonce(&self) -> u64 {
+        self.event_nonce.get().unwrap_or(0)
+    }
+
+    /// Get a page of a pool's APY history, oldest retained checkpoint first
+    pub fn get_pool_apy_history(
+        &self,
+        pool_name: String,
+        offset: u32,
+        limit: u32,
+    ) -> Vec<ApyCheckpoint> {
+        let count = self.pool_apy_history_count.get(&pool_name).unwrap_or(0);
+        let start = count.saturating_sub(APY_HISTORY_SIZE) + offset as u64;
+        let end = count.min(start + limit as u64);
+        (start..end)
+            .filter_map(|i| {
+                self.pool_apy_history
+                    .get(&(pool_name.clone(), i % APY_HISTORY_SIZE))
+            })
+            .collect()
+    }
+
+    /// Get a pool's time-weighted average APY over the last window_seconds
+    pub fn get_time_weighted_apy(&self, pool_name: String, window_seconds: u64) -> u32 {
+        let history = self.get_pool_apy_history(pool_name, 0, APY_HISTORY_SIZE as u32);
+        let now = self.env().get_block_time();
+        let since = now.saturating_sub(window_seconds.saturating_mul(1000));
+
+        let mut weighted = U512::zero();
+        let mut elapsed = 0u64;
+        for (i, checkpoint) in history.iter().enumerate() {
+            // Each APY holds until the next checkpoint, the latest one until now
+            let until = history.get(i + 1).map(|next| next.timestamp).unwrap_or(now);
+            let from = checkpoint.timestamp.max(since);
+            if until <= from {
+                continue;
+            }
+            weighted += U512::from(checkpoint.apy) * U512::from(until - from);
+            elapsed += until - from;
+        }
+
+        match (elapsed, history.last()) {
+            (0, Some(latest)) => latest.apy,
+            (0, None) => 0,
+            _ => (weighted / U512::from(elapsed)).as_u32(),
+        }
+    }
+
+    /// Set the management fee in basis points (owner only)
+    pub fn set_management_fee(&mut self, fee_bp: u32) {
+        self.require_owner();
+        if fee_bp > 10000 {
+            self.env().revert(Error::InvalidManagementFee)
+        }
+
+        let old_fee = self.get_management_fee();
+        self.management_fee.set(fee_bp);
+
+        self.env().emit_event(ManagementFeeUpdated {
+            old_fee,
+            new_fee: fee_bp,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Get management fee
+    pub fn get_management_fee(&self) -> u32 {
+        self.management_fee.get().unwrap_or(0)
+    }
+
+    /// Set deposit limits, zero caps mean unlimited (owner only)
+    pub fn set_limits(&mut self, max_tvl: U512, max_deposit_per_user: U512, min_deposit: U512) {
+        self.require_owner();
+
+        self.max_tvl.set(max_tvl);
+        self.max_deposit_per_user.set(max_deposit_per_user);
+        self.min_deposit.set(min_deposit);
+
+        self.env().emit_event(LimitsUpdated {
+            max_tvl,
+            max_deposit_per_user,
+            min_deposit,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Get deposit limits
+    pub fn get_limits(&self) -> VaultLimits {
+        VaultLimits {
+            max_tvl: self.max_tvl.get().unwrap_or(U512::zero()),
+            max_deposit_per_user: self.max_deposit_per_user.get().unwrap_or(U512::zero()),
+            min_deposit: self.min_deposit.get().unwrap_or(U512::zero()),
+        }
+    }
+
+    /// Get the largest amount a user can deposit right now
+    pub fn max_deposit(&self, user: Address) -> U512 {
+        if self.is_operation_paused(Operation::Deposit)
+            || !self.can_deposit(user)
+            || self.is_blocked(user)
+        {
+            return U512::zero();
+        }
+
+        let limits = self.get_limits();
+        let mut available = U512::MAX;
+
+        if limits.max_tvl > U512::zero() {
+            available = available.min(limits.max_tvl.saturating_sub(self.get_tvl()));
+        }
+        if limits.max_deposit_per_user > U512::zero() {
+            let deposited = self.get_user_position(&user).deposited_amount;
+            available = available.min(limits.max_deposit_per_user.saturating_sub(deposited));
+        }
+
+        if available < limits.min_deposit {
+            return U512::zero();
+        }
+        available
+    }
+
+    /// Restrict deposits to allowlisted addresses (owner only)
+    pub fn set_allowlist_enabled(&mut self, enabled: bool) {
+        self.require_owner();
+        self.allowlist_enabled.set(enabled);
+
+        self.env().emit_event(AllowlistToggled {
+            enabled,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Allow an address to deposit (owner only)
+    pub fn add_to_allowlist(&mut self, account: Address) {
+        self.require_owner();
+        self.set_allowlisted(account, true);
+    }
+
+    /// Revoke an address' deposit access, existing holdings stay withdrawable (owner only)
+    pub fn remove_from_allowlist(&mut self, account: Address) {
+        self.require_owner();
+        self.set_allowlisted(account, false);
+    }
+
+    /// Allow several addresses to deposit (owner only)
+    pub fn add_to_allowlist_batch(&mut self, accounts: Vec<Address>) {
+        self.require_owner();
+        for account in accounts {
+            self.set_allowlisted(account, true);
+        }
+    }
+
+    /// Revoke deposit access for several addresses (owner only)
+    pub fn remove_from_allowlist_batch(&mut self, accounts: Vec<Address>) {
+        self.require_owner();
+        for account in accounts {
+            self.set_allowlisted(account, false);
+        }
+    }
+
+    /// Check if deposits are restricted to the allowlist
+    pub fn is_allowlist_enabled(&self) -> bool {
+        self.allowlist_enabled.get().unwrap_or(false)
+    }
+
+    /// Check if an address is on the allowlist
+    pub fn is_allowlisted(&self, account: Address) -> bool {
+        self.allowlist.get(&account).unwrap_or(false)
+    }
+
+    /// Grant or revoke the guardian role (owner only)
+    pub fn set_guardian(&mut self, guardian: Address, enabled: bool) {
+        self.require_owner();
+        self.guardians.set(&guardian, enabled);
+
+        self.env().emit_event(GuardianUpdated {
+            guardian,
+            enabled,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Check if an address is a guardian
+    pub fn is_guardian(&self, account: Address) -> bool {
+        self.guardians.get(&account).unwrap_or(false)
+    }
+
+    /// Freeze an address: no deposits, withdrawals or share transfers (guardian only)
+    pub fn block_address(&mut self, account: Address) {
+        let guardian = self.require_guardian();
+        self.blocklist.set(&account, true);
+
+        self.env().emit_event(AddressBlocked {
+            account,
+            guardian,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Lift the freeze on an address (guardian only)
+    pub fn unblock_address(&mut self, account: Address) {
+        let guardian = self.require_guardian();
+        self.blocklist.set(&account, false);
+
+        self.env().emit_event(AddressUnblocked {
+            account,
+            guardian,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Check if an address is blocked
+    pub fn is_blocked(&self, account: Address) -> bool {
+        self.blocklist.get(&account).unwrap_or(false)
+    }
+
+    /// Start the timelock for moving a blocked position to a new address (owner only)
+    pub fn queue_position_recovery(&mut self, from: Address, to: Address) {
+        self.require_owner();
+        if !self.is_blocked(from) {
+            self.env().revert(Error::AddressNotBlocked)
+        }
+        self.require_not_blocked(to);
+
+        let executable_at = self.env().get_block_time() + POSITION_RECOVERY_DELAY;
+        self.pending_recoveries
+            .set(&from, Some(PendingRecovery { to, executable_at }));
+
+        self.env().emit_event(PositionRecoveryQueued {
+            from,
+            to,
+            executable_at,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Cancel a queued position recovery (owner only)
+    pub fn cancel_position_recovery(&mut self, from: Address) {
+        self.require_owner();
+        if self.get_pending_recovery(from).is_none() {
+            self.env().revert(Error::RecoveryNotQueued)
+        }
+        self.pending_recoveries.set(&from, None);
+
+        self.env().emit_event(PositionRecoveryCancelled {
+            from,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Move a blocked position to a new address once the timelock has passed (owner only)
+    pub fn recover_blocked_position(&mut self, from: Address, to: Address) {
+        self.require_owner();
+
+        let recovery = match self.get_pending_recovery(from) {
+            Some(r) if r.to == to => r,
+            _ => self.env().revert(Error::RecoveryNotQueued),
+        };
+        if self.env().get_block_time() < recovery.executable_at {
+            self.env().revert(Error::RecoveryTimelocked)
+        }
+        if !self.is_blocked(from) {
+            self.env().revert(Error::AddressNotBlocked)
+        }
+        self.require_not_blocked(to);
+
+        self.pending_recoveries.set(&from, None);
+        if self.get_lock(from).is_some() {
+            self.release_lock(from);
+        }
+        self.accrue_management_fee(from);
+        let shares = self.get_user_position(&from).shares;
+        if shares > U512::zero() {
+            self.move_shares(from, to, shares);
+        }
+
+        self.env().emit_event(PositionRecovered {
+            from,
+            to,
+            shares,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Get the queued recovery of a blocked position
+    pub fn get_pending_recovery(&self, from: Address) -> Option<PendingRecovery> {
+        self.pending_recoveries.get(&from).flatten()
+    }
+
+    /// Emergency pause of every operation (owner only)
+    pub fn pause(&mut self) {
+        self.require_owner();
+        self.set_pause_flags(true, true, true, true, String::new());
+    }
+
+    /// Clear every pause flag, emergency mode is left unchanged (owner only)
+    pub fn unpause(&mut self) {
+        self.require_owner();
+        self.set_pause_flags(false, false, false, false, String::new());
+    }
+
+    /// Pause or resume individual operations (owner only)
+    pub fn set_pause_flags(
+        &mut self,
+        deposits: bool,
+        withdrawals: bool,
+        allocations: bool,
+        harvests: bool,
+        reason: String,
+    ) {
+        self.require_owner();
+
+        let mut state = self.get_pause_state();
+        state.deposits_paused = deposits;
+        state.withdrawals_paused = withdrawals;
+        state.allocations_paused = allocations;
+        state.harvests_paused = harvests;
+        self.update_pause_state(state, reason);
+    }
+
+    /// Freeze deposits and allocations while keeping withdrawals open (owner only)
+    pub fn enter_emergency_mode(&mut self, reason: String) {
+        self.require_owner();
+
+        let mut state = self.get_pause_state();
+        if self.pause_expired(&state) {
+            // Expired flags must not come back into force with emergency mode
+            state.deposits_paused = false;
+            state.withdrawals_paused = false;
+            state.allocations_paused = false;
+            state.harvests_paused = false;
+        }
+        state.emergency_mode = true;
+        self.update_pause_state(state, reason);
+    }
+
+    /// Leave emergency mode (owner only)
+    pub fn exit_emergency_mode(&mut self) {
+        self.require_owner();
+
+        let mut state = self.get_pause_state();
+        state.emergency_mode = false;
+        self.update_pause_state(state, String::new());
+    }
+
+    /// Extend an active pause beyond the maximum duration (unanimous committee only)
+    pub fn extend_pause(&mut self, until: u64) {
+        self.require_committee();
+
+        let mut state = self.get_pause_state();
+        let expires_at = match self.pause_expiry(&state) {
+            Some(expiry) if !self.pause_expired(&state) => expiry,
+            _ => self.env().revert(Error::NotPaused),
+        };
+        if until <= expires_at {
+            self.env().revert(Error::InvalidPauseExtension)
+        }
+
+        state.extended_until = until;
+        self.pause_state.set(state);
+
+        self.env().emit_event(PauseExtended {
+            expires_at: until,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Set the maximum pause duration, 0 disables expiry (owner only)
+    ///
+    /// While a pause is in force or within the maximum duration after it
+    /// ended, the change needs every signer, like `extend_pause`.
+    pub fn set_max_pause_duration(&mut self, duration: u64) {
+        self.require_owner();
+        if self.pause_in_force() || self.pause_recently_ended() {
+            self.require_committee();
+        }
+
+        let old_duration = self.get_max_pause_duration();
+        self.max_pause_duration.set(duration);
+
+        self.env().emit_event(MaxPauseDurationUpdated {
+            old_duration,
+            new_duration: duration,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Get the maximum pause duration
+    pub fn get_max_pause_duration(&self) -> u64 {
+        self.max_pause_duration.get().unwrap_or(0)
+    }
+
+    /// Check if any operation is effectively paused, and when the pause expires
+    pub fn is_paused(&self) -> PauseStatus {
+        let state = self.get_pause_state();
+        let expired = self.pause_expired(&state);
+
+        PauseStatus {
+            paused: (state.any_flag_set() && !expired) || state.emergency_mode,
+            expires_at: if expired {
+                None
+            } else {
+                self.pause_expiry(&state)
+            },
+        }
+    }
+
+    /// Get pause flags, reason and timestamp
+    pub fn get_pause_state(&self) -> PauseState {
+        self.pause_state.get().unwrap_or_default()
+    }
+
+    /// Unwind every pool allocation and settle users pro-rata (owner only)
+    ///
+    /// Funds that cannot be recovered are written off against TVL. Deposits
+    /// and allocations are disabled permanently, withdrawals stay open.
+    pub fn emergency_exit(&mut self) {
+        self.require_owner();
+        if self.is_exited() {
+            self.env().revert(Error::VaultExited)
+        }
+
+        let mut recoveries = Vec::new();
+        let mut total_allocated = U512::zero();
+        let mut total_recovered = U512::zero();
+
+        for name in self.get_pool_names() {
+            let mut pool = match self.pools.get(&name) {
+                Some(p) => p,
+                None => continue,
+            };
+            // Book auction rewards not synced yet so they reach holders
+            if let Some(validator) = pool.validator.clone() {
+                pool = self.sync_delegation_internal(pool, validator);
+            }
+
+            let allocated = pool.total_allocated;
+            let recovered = self.recover_pool_funds(&pool);
+            total_allocated += allocated;
+            total_recovered += recovered;
+
+            pool.total_allocated = U512::zero();
+            self.pools.set(&name, pool);
+
+            recoveries.push((name, recovered));
+        }
+
+        // Record any shortfall as a loss shared by all holders
+        let loss = total_allocated.saturating_sub(total_recovered);
+        let current_tvl = self.total_tvl.get().unwrap_or(U512::zero());
+        self.total_tvl.set(current_tvl.saturating_sub(loss));
+        self.total_allocated.set(U512::zero());
+        self.exited.set(true);
+
+        self.env().emit_event(EmergencyExit {
+            recovered: recoveries,
+            total_recovered,
+            loss,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Check if the vault has been unwound by an emergency exit
+    pub fn is_exited(&self) -> bool {
+        self.exited.get().unwrap_or(false)
+    }
+
+    // ========== Batch Operations ==========
+    // Each batch reverts as a whole if any single operation fails
+
+    /// Add several pools (owner only)
+    pub fn add_pools(&mut self, pools: Vec<NewPool>) {
+        for pool in pools {
+            self.add_pool(pool.name, pool.initial_apy, pool.risk_level);
+        }
+    }
+
+    /// Update the APY of several pools (owner or oracle)
+    pub fn update_apys(&mut self, updates: Vec<ApyUpdate>) {
+        for update in updates {
+            self.update_pool_apy(update.pool, update.apy);
+        }
+    }
+
+    /// Allocate to several pools (owner only)
+    pub fn allocate_many(&mut self, allocations: Vec<PoolAllocation>) {
+        for allocation in allocations {
+            self.allocate_to_pool(allocation.pool, allocation.amount);
+        }
+    }
+
+    // ========== Delegation Pools ==========
+
+    /// Add a pool that delegates its allocation to a validator (owner only)
+    pub fn add_delegation_pool(&mut self, name: String, validator: PublicKey, risk_level: u8) {
+        self.require_owner();
+
+        if self.pools.get(&name).is_some() {
+            self.env().revert(Error::PoolAlreadyExists)
+        }
+
+        let pool = PoolInfo {
+            name: name.clone(),
+            total_allocated: U512::zero(),
+            current_apy: 0,
+            risk_level,
+            validator: Some(validator),
+            apy_updated_at: self.env().get_block_time(),
+        };
+
+        let mut names = self.get_pool_names();
+        names.push(name.clone());
+        self.pool_names.set(names);
+        self.pools.set(&name, pool);
+        self.record_apy_checkpoint(&name, 0);
+
+        self.env().emit_event(PoolAdded {
+            pool: name,
+            initial_apy: 0,
+            risk_level,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Send native CSPR to the vault purse so it can be delegated
+    #[odra(payable)]
+    pub fn fund_vault(&mut self) {
+        let amount = self.env().attached_value();
+        self.require_positive_amount(amount);
+
+        self.env().emit_event(VaultFunded {
+            from: self.env().caller(),
+            amount,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Book auction rewards or slashing of a delegation pool (keeper only)
+    pub fn sync_delegation(&mut self, pool_name: String) {
+        self.require_keeper();
+        self.require_not_paused(Operation::Harvest);
+
+        let pool = match self.pools.get(&pool_name) {
+            Some(p) => p,
+            None => self.env().revert(Error::PoolNotFound),
+        };
+        let validator = match pool.validator.clone() {
+            Some(v) => v,
+            None => self.env().revert(Error::PoolNotFound),
+        };
+        self.sync_delegation_internal(pool, validator);
+    }
+
+    /// Get the amount currently delegated by a delegation pool
+    pub fn get_delegated_amount(&self, pool_name: String) -> U512 {
+        match self.pools.get(&pool_name).and_then(|p| p.validator) {
+            Some(validator) => self.env().delegated_amount(validator),
+            None => U512::zero(),
+        }
+    }
+
+    // ========== Unbonding ==========
+
+    /// Move matured unbonding stake to idle liquidity (keeper only)
+    pub fn claim_unbonded(&mut self) -> U512 {
+        self.require_keeper();
+
+        let now = self.env().get_block_time();
+        let mut claimed = U512::zero();
+        let mut remaining_validators = Vec::new();
+
+        for validator in self.unbonding_validators.get().unwrap_or_default() {
+            let (matured, pending): (Vec<_>, Vec<_>) = self
+                .get_unbonding(validator.clone())
+                .into_iter()
+                .partition(|entry| entry.available_at <= now);
+
+            for entry in matured {
+                claimed += entry.amount;
+            }
+            if !pending.is_empty() {
+                remaining_validators.push(validator.clone());
+            }
+            self.unbonding.set(&validator, pending);
+        }
+
+        self.unbonding_validators.set(remaining_validators);
+        let total_unbonding = self.get_total_unbonding();
+        self.total_unbonding.set(total_unbonding - claimed);
+
+        if claimed > U512::zero() {
+            self.env().emit_event(UnbondedClaimed {
+                amount: claimed,
+                timestamp: now,
+                nonce: self.next_event_nonce(),
+            });
+        }
+
+        claimed
+    }
+
+    /// Set the expected unbonding period in milliseconds (owner only)
+    pub fn set_unbonding_period(&mut self, period: u64) {
+        self.require_owner();
+        self.unbonding_period.set(period);
+
+        self.env().emit_event(UnbondingPeriodUpdated {
+            period,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Get the expected unbonding period
+    pub fn get_unbonding_period(&self) -> u64 {
+        self.unbonding_period
+            .get()
+            .unwrap_or(DEFAULT_UNBONDING_PERIOD)
+    }
+
+    /// Get the unbonding ledger of a validator
+    pub fn get_unbonding(&self, validator: PublicKey) -> Vec<UnbondingEntry> {
+        self.unbonding.get(&validator).unwrap_or_default()
+    }
+
+    /// Get the sum of all unbonding stake
+    pub fn get_total_unbonding(&self) -> U512 {
+        self.total_unbonding.get().unwrap_or(U512::zero())
+    }
+
+    /// Get idle, allocated and unbonding funds combined
+    pub fn total_assets(&self) -> U512 {
+        self.get_idle_liquidity() + self.get_total_allocated() + self.get_total_unbonding()
+    }
+
+    // ========== Delegation Strategy ==========
+
+    /// Set a delegation pool's target weight and bounds, a zero weight removes it (owner only)
+    pub fn set_validator_target(
+        &mut self,
+        pool_name: String,
+        weight: u32,
+        min_delegation: U512,
+        max_delegation: U512,
+    ) {
+        self.require_owner();
+
+        match self.pools.get(&pool_name) {
+            None => self.env().revert(Error::PoolNotFound),
+            Some(pool) if pool.validator.is_none() => self.env().revert(Error::NotDelegationPool),
+            Some(_) => {}
+        }
+        if max_delegation > U512::zero() && max_delegation < min_delegation {
+            self.env().revert(Error::InvalidValidatorTarget)
+        }
+
+        let mut strategy = self.get_strategy_pools();
+        strategy.retain(|name| name != &pool_name);
+        let other_weights = self.total_target_weight()
+            - self
+                .get_validator_target(pool_name.clone())
+                .map(|t| t.weight)
+                .unwrap_or(0);
+        if other_weights + weight > 10000 {
+            self.env().revert(Error::InvalidValidatorTarget)
+        }
+
+        if weight == 0 {
+            if self.get_validator_target(pool_name.clone()).is_some() {
+                self.unbond_pool(pool_name.clone());
+            }
+            self.validator_targets.set(&pool_name, None);
+        } else {
+            strategy.push(pool_name.clone());
+            self.validator_targets.set(
+                &pool_name,
+                Some(ValidatorTarget {
+                    weight,
+                    min_delegation,
+                    max_delegation,
+                }),
+            );
+        }
+        self.strategy_pools.set(strategy);
+
+        self.env().emit_event(ValidatorTargetUpdated {
+            pool: pool_name,
+            weight,
+            min_delegation,
+            max_delegation,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Unbond stake of removed validators and delegate idle funds by target weight (keeper only)
+    pub fn rebalance_delegations(&mut self) {
+        self.require_keeper();
+        self.require_not_paused(Operation::Allocation);
+        if self
+            .get_strategy_pools()
+            .iter()
+            .any(|name| self.is_pool_stale(name))
+        {
+            self.env().revert(Error::StaleApy)
+        }
+
+        // A removed validator's stake is force-unbonded by the auction
+        let mut unbonded = U512::zero();
+        for name in self.get_strategy_pools() {
+            let mut pool = match self.pools.get(&name) {
+                Some(p) => p,
+                None => continue,
+            };
+            let validator = match pool.validator.clone() {
+                Some(v) => v,
+                None => continue,
+            };
+            if pool.total_allocated == U512::zero()
+                || self.env().delegated_amount(validator.clone()) > U512::zero()
+            {
+                continue;
+            }
+
+            let amount = pool.total_allocated;
+            self.record_unbonding(validator, name.clone(), amount);
+            pool.total_allocated = U512::zero();
+            self.pools.set(&name, pool);
+            let total_allocated = self.get_total_allocated();
+            self.total_allocated.set(total_allocated - amount);
+
+            self.validator_targets.set(&name, None);
+            let mut strategy = self.get_strategy_pools();
+            strategy.retain(|n| n != &name);
+            self.strategy_pools.set(strategy);
+
+            unbonded += amount;
+        }
+
+        // Only motes actually held by the vault can be delegated
+        let mut available = self.get_idle_liquidity().min(self.env().self_balance());
+        let strategy = self.get_strategy_pools();
+        let total_stake = strategy
+            .iter()
+            .filter_map(|name| self.pools.get(name))
+            .fold(available, |acc, pool| acc + pool.total_allocated);
+
+        let total_weight = U512::from(self.total_target_weight());
+        let mut delegated = U512::zero();
+        for name in strategy {
+            let (mut pool, target) = match (
+                self.pools.get(&name),
+                self.get_validator_target(name.clone()),
+            ) {
+                (Some(p), Some(t)) => (p, t),
+                _ => continue,
+            };
+            let validator = match pool.validator.clone() {
+                Some(v) => v,
+                None => continue,
+            };
+
+            let mut target_stake = total_stake * U512::from(target.weight) / total_weight;
+            if target.max_delegation > U512::zero() {
+                target_stake = target_stake.min(target.max_delegation);
+            }
+            if target_stake < target.min_delegation {
+                continue;
+            }
+
+            let amount = target_stake
+                .saturating_sub(pool.total_allocated)
+                .min(available);
+            if amount == U512::zero() {
+                continue;
+            }
+
+            self.env().delegate(validator, amount);
+            pool.total_allocated += amount;
+            self.pools.set(&name, pool);
+            let total_allocated = self.get_total_allocated();
+            self.total_allocated.set(total_allocated + amount);
+
+            self.env().emit_event(PoolAllocated {
+                pool: name,
+                amount,
+                timestamp: self.env().get_block_time(),
+                nonce: self.next_event_nonce(),
+            });
+
+            available -= amount;
+            delegated += amount;
+        }
+
+        self.env().emit_event(DelegationsRebalanced {
+            delegated,
+            unbonded,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Get the strategy target of a delegation pool
+    pub fn get_validator_target(&self, pool_name: String) -> Option<ValidatorTarget> {
+        self.validator_targets.get(&pool_name).flatten()
+    }
+
+    /// Get delegation pools in the staking strategy
+    pub fn get_strategy_pools(&self) -> Vec<String> {
+        self.strategy_pools.get().unwrap_or_default()
+    }
+
+    /// Get actual against target weights of the strategy's delegation pools
+    pub fn get_validator_weights(&self) -> Vec<ValidatorWeight> {
+        let pools: Vec<PoolInfo> = self
+            .get_strategy_pools()
+            .iter()
+            .filter_map(|name| self.pools.get(name))
+            .collect();
+        let total = pools
+            .iter()
+            .fold(U512::zero(), |acc, pool| acc + pool.total_allocated);
+        let total_weight = self.total_target_weight();
+
+        pools
+            .into_iter()
+            .map(|pool| {
+                let actual_weight = if total == U512::zero() {
+                    0
+                } else {
+                    (pool.total_allocated * U512::from(10000u32) / total).as_u32()
+                };
+                let weight = self
+                    .get_validator_target(pool.name.clone())
+                    .map(|t| t.weight)
+                    .unwrap_or(0);
+                ValidatorWeight {
+                    target_weight: weight * 10000 / total_weight.max(1),
+                    pool: pool.name,
+                    delegated: pool.total_allocated,
+                    actual_weight,
+                }
+            })
+            .collect()
+    }
+
+    /// Get names of all pools
+    pub fn get_pool_names(&self) -> Vec<String> {
+        self.pool_names.get().unwrap_or_default()
+    }
+
+    /// Get sum of all pool allocations
+    pub fn get_total_allocated(&self) -> U512 {
+        self.total_allocated.get().unwrap_or(U512::zero())
+    }
+
+    /// Get funds not allocated to any pool
+    pub fn get_idle_liquidity(&self) -> U512 {
+        self.get_tvl()
+            .saturating_sub(self.get_total_allocated())
+            .saturating_sub(self.get_total_unbonding())
+    }
+
+    // ========== Cooldown ==========
+
+    /// Start the cooldown on shares, replacing any previous cooldown
+    pub fn start_cooldown(&mut self, shares: U512) {
+        self.require_positive_amount(shares);
+
+        let caller = self.env().caller();
+        self.require_not_blocked(caller);
+        if self.get_user_position(&caller).shares < shares {
+            self.env().revert(Error::InsufficientShares)
+        }
+
+        let now = self.env().get_block_time();
+        let withdrawable_from = now + self.cooldown_period.get().unwrap_or(0);
+        let withdrawable_until = withdrawable_from + self.withdrawal_window.get().unwrap_or(0);
+        self.cooldowns.set(
+            &caller,
+            Some(Cooldown {
+                shares,
+                started_at: now,
+                withdrawable_from,
+                withdrawable_until,
+            }),
+        );
+
+        self.env().emit_event(CooldownStarted {
+            user: caller,
+            shares,
+            withdrawable_from,
+            withdrawable_until,
+            timestamp: now,
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Set cooldown and withdrawal window lengths, a zero cooldown disables it (owner only)
+    pub fn set_cooldown_config(&mut self, cooldown_period: u64, withdrawal_window: u64) {
+        self.require_owner();
+        if cooldown_period > 0 && withdrawal_window == 0 {
+            self.env().revert(Error::InvalidCooldownConfig)
+        }
+
+        self.cooldown_period.set(cooldown_period);
+        self.withdrawal_window.set(withdrawal_window);
+
+        self.env().emit_event(CooldownConfigUpdated {
+            cooldown_period,
+            withdrawal_window,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Get cooldown period and withdrawal window lengths
+    pub fn get_cooldown_config(&self) -> (u64, u64) {
+        (
+            self.cooldown_period.get().unwrap_or(0),
+            self.withdrawal_window.get().unwrap_or(0),
+        )
+    }
+
+    /// Get a user's active cooldown
+    pub fn get_cooldown(&self, user: Address) -> Option<Cooldown> {
+        self.cooldowns.get(&user).flatten()
+    }
+
+    /// Check if a user can withdraw cooled-down shares right now
+    pub fn is_in_withdrawal_window(&self, user: Address) -> bool {
+        let now = self.env().get_block_time();
+        self.get_cooldown(user)
+            .map(|c| now >= c.withdrawable_from && now <= c.withdrawable_until)
+            .unwrap_or(false)
+    }
+
+    // ========== Withdrawal Queue ==========
+
+    /// Lock shares in the withdrawal queue, returning the request id
+    pub fn request_withdrawal(&mut self, shares: U512) -> u64 {
+        self.require_not_paused(Operation::Withdrawal);
+        self.require_positive_amount(shares);
+
+        let caller = self.env().caller();
+        self.require_not_blocked(caller);
+
+        self.accrue_management_fee(caller);
+        self.update_reward(Some(caller));
+        let mut position = self.get_user_position(&caller);
+        if position.shares < shares {
+            self.env().revert(Error::InsufficientShares)
+        }
+        self.consume_cooldown(caller, shares);
+        self.require_unlocked(caller, shares);
+
+        let queued_shares = self.queued_shares.get().unwrap_or(U512::zero());
+        self.queued_shares.set(queued_shares + shares);
+
+        // Locked shares leave the position but stay in the supply until processed
+        let deposited = position.deposited_amount * shares / position.shares;
+        position.deposited_amount -= deposited;
+        position.shares -= shares;
+        if position.shares == U512::zero() {
+            position.total_rewards = U512::zero();
+        }
+        self.user_positions.set(&caller, position);
+        self.checkpoint_shares(caller);
+
+        let request_id = self.next_withdrawal_id.get().unwrap_or(0);
+        self.next_withdrawal_id.set(request_id + 1);
+
+        self.withdrawal_requests.set(
+            &request_id,
+            WithdrawalRequest {
+                id: request_id,
+                user: caller,
+                shares,
+                amount: U512::zero(),
+                requested_at: self.env().get_block_time(),
+                processed: false,
+                claimed: false,
+            },
+        );
+
+        let mut ids = self
+            .user_withdrawal_requests
+            .get(&caller)
+            .unwrap_or_default();
+        ids.push(request_id);
+        self.user_withdrawal_requests.set(&caller, ids);
+
+        self.env().emit_event(WithdrawalRequested {
+            request_id,
+            user: caller,
+            shares,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+
+        request_id
+    }
+
+    /// Price and settle queued requests in order while idle funds last (keeper only)
+    pub fn process_withdrawals(&mut self, max_requests: u32) {
+        self.require_keeper();
+
+        let mut head = self.withdrawal_queue_head.get().unwrap_or(0);
+        let end = self.next_withdrawal_id.get().unwrap_or(0);
+        let mut processed = 0u32;
+
+        while head < end && processed < max_requests {
+            let mut request = match self.withdrawal_requests.get(&head) {
+                Some(r) => r,
+                None => break,
+            };
+
+            let amount = self.calculate_withdrawal_amount(request.shares);
+            if self.get_idle_liquidity() < amount {
+                // Keep FIFO order until keepers free up more liquidity
+                break;
+            }
+
+            let current_tvl = self.get_tvl();
+            self.total_tvl.set(current_tvl - amount);
+            let current_shares = self.get_total_shares();
+            self.total_shares.set(current_shares - request.shares);
+            let queued_shares = self.queued_shares.get().unwrap_or(U512::zero());
+            self.queued_shares.set(queued_shares - request.shares);
+            let reserved = self.get_reserved_for_withdrawals();
+            self.reserved_for_withdrawals.set(reserved + amount);
+
+            request.amount = amount;
+            request.processed = true;
+            self.withdrawal_requests.set(&head, request.clone());
+
+            self.env().emit_event(WithdrawalProcessed {
+                request_id: head,
+                user: request.user,
+                shares: request.shares,
+                amount,
+                timestamp: self.env().get_block_time(),
+                nonce: self.next_event_nonce(),
+            });
+
+            head += 1;
+            processed += 1;
+        }
+
+        self.withdrawal_queue_head.set(head);
+    }
+
+    /// Claim the proceeds of a processed withdrawal request
+    pub fn claim_withdrawal(&mut self, request_id: u64) {
+        self.require_not_paused(Operation::Withdrawal);
+
+        let caller = self.env().caller();
+        self.require_not_blocked(caller);
+
+        let mut request = match self.withdrawal_requests.get(&request_id) {
+            Some(r) => r,
+            None => self.env().revert(Error::WithdrawalRequestNotFound),
+        };
+        if request.user != caller {
+            self.env().revert(Error::NotRequestOwner)
+        }
+        if !request.processed {
+            self.env().revert(Error::WithdrawalNotProcessed)
+        }
+        if request.claimed {
+            self.env().revert(Error::WithdrawalAlreadyClaimed)
+        }
+
+        request.claimed = true;
+        self.withdrawal_requests.set(&request_id, request.clone());
+
+        let reserved = self.get_reserved_for_withdrawals();
+        self.reserved_for_withdrawals.set(reserved - request.amount);
+
+        self.env().emit_event(WithdrawalClaimed {
+            request_id,
+            user: caller,
+            amount: request.amount,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Return pool funds to idle liquidity (keeper only)
+    pub fn deallocate_from_pool(&mut self, pool_name: String, amount: U512) {
+        self.require_keeper();
+        self.require_positive_amount(amount);
+
+        let mut pool = match self.pools.get(&pool_name) {
+            Some(p) => p,
+            None => self.env().revert(Error::PoolNotFound),
+        };
+        if pool.total_allocated < amount {
+            self.env().revert(Error::InsufficientAllocation)
+        }
+
+        if let Some(validator) = pool.validator.clone() {
+            self.env().undelegate(validator.clone(), amount);
+            self.record_unbonding(validator, pool_name.clone(), amount);
+        }
+
+        pool.total_allocated -= amount;
+        self.pools.set(&pool_name, pool);
+
+        let total_allocated = self.get_total_allocated();
+        self.total_allocated.set(total_allocated - amount);
+
+        self.env().emit_event(PoolDeallocated {
+            pool: pool_name,
+            amount,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Grant or revoke the keeper role (owner only)
+    pub fn set_keeper(&mut self, keeper: Address, enabled: bool) {
+        self.require_owner();
+        self.keepers.set(&keeper, enabled);
+
+        self.env().emit_event(KeeperUpdated {
+            keeper,
+            enabled,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Grant or revoke the APY oracle role (owner only)
+    pub fn set_oracle(&mut self, oracle: Address, enabled: bool) {
+        self.require_owner();
+        self.oracles.set(&oracle, enabled);
+
+        self.env().emit_event(OracleUpdated {
+            oracle,
+            enabled,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Check if an address is an APY oracle
+    pub fn is_oracle(&self, account: Address) -> bool {
+        self.oracles.get(&account).unwrap_or(false)
+    }
+
+    /// Set the maximum APY change per update and the APY staleness threshold (owner only)
+    pub fn set_apy_guards(&mut self, max_apy_change: u32, max_apy_age: u64) {
+        self.require_owner();
+        self.max_apy_change.set(max_apy_change);
+        self.max_apy_age.set(max_apy_age);
+
+        self.env().emit_event(ApyGuardsUpdated {
+            max_apy_change,
+            max_apy_age,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Get the maximum APY change per update and the APY staleness threshold
+    pub fn get_apy_guards(&self) -> (u32, u64) {
+        (
+            self.max_apy_change.get().unwrap_or(0),
+            self.max_apy_age.get().unwrap_or(0),
+        )
+    }
+
+    /// Get pools whose APY has not been updated within the staleness threshold
+    pub fn get_stale_pools(&self) -> Vec<String> {
+        self.get_pool_names()
+            .into_iter()
+            .filter(|name| self.is_pool_stale(name))
+            .collect()
+    }
+
+    /// Check if an address is a keeper
+    pub fn is_keeper(&self, account: Address) -> bool {
+        self.keepers.get(&account).unwrap_or(false)
+    }
+
+    /// Get a withdrawal request
+    pub fn get_withdrawal_request(&self, request_id: u64) -> Option<WithdrawalRequest> {
+        self.withdrawal_requests.get(&request_id)
+    }
+
+    /// Get a user's unclaimed withdrawal requests
+    pub fn get_pending_withdrawals(&self, user: Address) -> Vec<WithdrawalRequest> {
+        self.user_withdrawal_requests
+            .get(&user)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|id| self.withdrawal_requests.get(id))
+            .filter(|request| !request.claimed)
+            .collect()
+    }
+
+    /// Get number of requests waiting to be processed
+    pub fn get_withdrawal_queue_depth(&self) -> u64 {
+        let head = self.withdrawal_queue_head.get().unwrap_or(0);
+        self.next_withdrawal_id.get().unwrap_or(0) - head
+    }
+
+    /// Get processed withdrawals not yet claimed
+    pub fn get_reserved_for_withdrawals(&self) -> U512 {
+        self.reserved_for_withdrawals.get().unwrap_or(U512::zero())
+    }
+
+    // ========== Admin Committee ==========
+
+    /// Propose a privileged entry point call (signers only)
+    ///
+    /// `args` are the serialized `RuntimeArgs` of the call. The proposer's
+    /// approval is counted immediately, so a 1-of-N committee executes at once.
+    pub fn propose(&mut self, entry_point: String, args: Bytes) -> u32 {
+        let caller = self.env().caller();
+        self.require_signer(caller);
+
+        if RuntimeArgs::from_bytes(&args).is_err() {
+            self.env().revert(Error::InvalidProposalArgs)
+        }
+
+        let proposal_id = self.proposal_count.get().unwrap_or(0);
+        self.proposal_count.set(proposal_id + 1);
+
+        self.proposals.set(
+            &proposal_id,
+            Proposal {
+                id: proposal_id,
+                proposer: caller,
+                entry_point: entry_point.clone(),
+                args,
+                approvals: 0,
+                executed: false,
+                created_at: self.env().get_block_time(),
+            },
+        );
+
+        self.env().emit_event(ProposalCreated {
+            proposal_id,
+            proposer: caller,
+            entry_point,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+
+        self.approve(proposal_id);
+        proposal_id
+    }
+
+    /// Approve a pending proposal (signers only), executing it once the threshold is met
+    pub fn approve(&mut self, proposal_id: u32) {
+        let caller = self.env().caller();
+        self.require_signer(caller);
+
+        let mut proposal = match self.proposals.get(&proposal_id) {
+            Some(p) => p,
+            None => self.env().revert(Error::ProposalNotFound),
+        };
+
+        if proposal.executed {
+            self.env().revert(Error::ProposalAlreadyExecuted)
+        }
+        if self.has_approved(proposal_id, caller) {
+            self.env().revert(Error::AlreadyApproved)
+        }
+
+        self.proposal_approvals.set(&(proposal_id, caller), true);
+
+        // Only approvals from current signers count
+        proposal.approvals = self
+            .get_signers()
+            .iter()
+            .filter(|signer| self.has_approved(proposal_id, **signer))
+            .count() as u32;
+
+        self.env().emit_event(ProposalApproved {
+            proposal_id,
+            signer: caller,
+            approvals: proposal.approvals,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+
+        if proposal.approvals >= self.required_approvals(&proposal.entry_point) {
+            proposal.executed = true;
+            self.proposals.set(&proposal_id, proposal.clone());
+            self.execute_proposal(proposal);
+        } else {
+            self.proposals.set(&proposal_id, proposal);
+        }
+    }
+
+    /// Add a committee signer (committee only)
+    pub fn add_signer(&mut self, signer: Address) {
+        self.require_committee();
+
+        let mut signers = self.get_signers();
+        if signers.contains(&signer) {
+            self.env().revert(Error::SignerAlreadyExists)
+        }
+        signers.push(signer);
+        self.signers.set(signers);
+
+        self.env().emit_event(SignerAdded {
+            signer,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Remove a committee signer (committee only)
+    pub fn remove_signer(&mut self, signer: Address) {
+        self.require_committee();
+
+        let mut signers = self.get_signers();
+        let index = match signers.iter().position(|s| *s == signer) {
+            Some(i) => i,
+            None => self.env().revert(Error::SignerNotFound),
+        };
+        signers.remove(index);
+
+        // The remaining committee must still be able to reach the threshold
+        if (signers.len() as u32) < self.get_threshold() {
+            self.env().revert(Error::InvalidThreshold)
+        }
+        self.signers.set(signers);
+
+        self.env().emit_event(SignerRemoved {
+            signer,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Change the number of approvals required (committee only)
+    pub fn set_threshold(&mut self, threshold: u32) {
+        self.require_committee();
+
+        if threshold == 0 || threshold > self.get_signers().len() as u32 {
+            self.env().revert(Error::InvalidThreshold)
+        }
+
+        let old_threshold = self.get_threshold();
+        self.threshold.set(threshold);
+
+        self.env().emit_event(ThresholdChanged {
+            old_threshold,
+            new_threshold: threshold,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Get contract owner, the first committee signer
+    ///
+    /// Deprecated: use `get_signers` and `get_threshold`
+    pub fn get_owner(&self) -> Option<Address> {
+        self.get_signers().first().copied()
+    }
+
+    /// Get committee signers
+    pub fn get_signers(&self) -> Vec<Address> {
+        self.signers.get().unwrap_or_default()
+    }
+
+    /// Get number of approvals required
+    pub fn get_threshold(&self) -> u32 {
+        self.threshold.get().unwrap_or(1)
+    }
+
+    /// Check if an address is a committee signer
+    pub fn is_signer(&self, address: Address) -> bool {
+        self.get_signers().contains(&address)
+    }
+
+    /// Get proposal details
+    pub fn get_proposal(&self, proposal_id: u32) -> Option<Proposal> {
+        self.proposals.get(&proposal_id)
+    }
+
+    /// Get number of proposals created
+    pub fn get_proposal_count(&self) -> u32 {
+        self.proposal_count.get().unwrap_or(0)
+    }
+
+    /// Check if a signer approved a proposal
+    pub fn has_approved(&self, proposal_id: u32, signer: Address) -> bool {
+        self.proposal_approvals
+            .get(&(proposal_id, signer))
+            .unwrap_or(false)
+    }
+
+    // ========== Governance ==========
+
+    /// Put a governable entry point call to a shareholder vote
+    ///
+    /// Votes are weighted by share balances at creation; shares moved
+    /// afterwards don't change anyone's voting power on this proposal.
+    pub fn propose_governance(&mut self, entry_point: String, args: Bytes) -> u32 {
+        let caller = self.env().caller();
+        if !GOVERNABLE_ENTRY_POINTS.contains(&entry_point.as_str()) {
+            self.env().revert(Error::ActionNotGovernable)
+        }
+        if RuntimeArgs::from_bytes(&args).is_err() {
+            self.env().revert(Error::InvalidProposalArgs)
+        }
+        let now = self.env().get_block_time();
+        if self.get_past_shares(caller, now).is_zero() {
+            self.env().revert(Error::NoVotingPower)
+        }
+
+        let proposal_id = self.get_governance_proposal_count();
+        self.governance_proposal_count.set(proposal_id + 1);
+
+        let voting_ends_at = now + self.voting_period.get().unwrap_or(DEFAULT_VOTING_PERIOD);
+        self.governance_proposals.set(
+            &proposal_id,
+            GovernanceProposal {
+                id: proposal_id,
+                proposer: caller,
+                entry_point: entry_point.clone(),
+                args,
+                votes_for: U512::zero(),
+                votes_against: U512::zero(),
+                voting_ends_at,
+                executed: false,
+                created_at: now,
+            },
+        );
+
+        self.env().emit_event(GovernanceProposalCreated {
+            proposal_id,
+            proposer: caller,
+            entry_point,
+            voting_ends_at,
+            timestamp: now,
+            nonce: self.next_event_nonce(),
+        });
+
+        proposal_id
+    }
+
+    /// Vote on a shareholder proposal with the caller's shares from before its creation
+    pub fn vote(&mut self, proposal_id: u32, support: bool) {
+        let caller = self.env().caller();
+        self.require_not_blocked(caller);
+        let mut proposal = self.require_governance_proposal(proposal_id);
+
+        if self.env().get_block_time() >= proposal.voting_ends_at {
+            self.env().revert(Error::VotingClosed)
+        }
+        if self.has_voted(proposal_id, caller) {
+            self.env().revert(Error::AlreadyVoted)
+        }
+        let weight = self.get_past_shares(caller, proposal.created_at);
+        if weight.is_zero() {
+            self.env().revert(Error::NoVotingPower)
+        }
+
+        self.governance_votes.set(&(proposal_id, caller), true);
+        if support {
+            proposal.votes_for += weight;
+        } else {
+            proposal.votes_against += weight;
+        }
+        self.governance_proposals.set(&proposal_id, proposal);
+
+        self.env().emit_event(VoteCast {
+            proposal_id,
+            voter: caller,
+            support,
+            weight,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Execute a shareholder proposal that passed with quorum once voting has ended
+    pub fn execute_governance_proposal(&mut self, proposal_id: u32) {
+        let mut proposal = self.require_governance_proposal(proposal_id);
+
+        if proposal.executed {
+            self.env().revert(Error::ProposalAlreadyExecuted)
+        }
+        if self.env().get_block_time() < proposal.voting_ends_at {
+            self.env().revert(Error::VotingActive)
+        }
+        if !self.is_governance_proposal_passed(proposal_id) {
+            self.env().revert(Error::QuorumNotReached)
+        }
+
+        proposal.executed = true;
+        self.governance_proposals
+            .set(&proposal_id, proposal.clone());
+        self.call_self(&proposal.entry_point, &proposal.args);
+
+        self.env().emit_event(GovernanceProposalExecuted {
+            proposal_id,
+            entry_point: proposal.entry_point,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Set the voting period and quorum of shareholder proposals (owner only)
+    pub fn set_governance_config(&mut self, voting_period: u64, quorum_bp: u32) {
+        self.require_owner();
+        if voting_period == 0 || quorum_bp == 0 || quorum_bp > 10000 {
+            self.env().revert(Error::InvalidGovernanceConfig)
+        }
+        self.voting_period.set(voting_period);
+        self.quorum_bp.set(quorum_bp);
+
+        self.env().emit_event(GovernanceConfigUpdated {
+            voting_period,
+            quorum_bp,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Get the voting period and quorum (basis points)
+    pub fn get_governance_config(&self) -> (u64, u32) {
+        (
+            self.voting_period.get().unwrap_or(DEFAULT_VOTING_PERIOD),
+            self.quorum_bp.get().unwrap_or(DEFAULT_QUORUM_BP),
+        )
+    }
+
+    /// Get shareholder proposal details
+    pub fn get_governance_proposal(&self, proposal_id: u32) -> Option<GovernanceProposal> {
+        self.governance_proposals.get(&proposal_id)
+    }
+
+    /// Get number of shareholder proposals created
+    pub fn get_governance_proposal_count(&self) -> u32 {
+        self.governance_proposal_count.get().unwrap_or(0)
+    }
+
+    /// Check if a shareholder voted on a proposal
+    pub fn has_voted(&self, proposal_id: u32, voter: Address) -> bool {
+        self.governance_votes
+            .get(&(proposal_id, voter))
+            .unwrap_or(false)
+    }
+
+    /// Check if a proposal has a majority in favour and reached quorum
+    pub fn is_governance_proposal_passed(&self, proposal_id: u32) -> bool {
+        let proposal = match self.get_governance_proposal(proposal_id) {
+            Some(p) => p,
+            None => return false,
+        };
+        let quorum = self.get_past_total_shares(proposal.created_at)
+            * U512::from(self.get_governance_config().1)
+            / U512::from(10000u32);
+        proposal.votes_for > proposal.votes_against && proposal.votes_for >= quorum
+    }
+
+    /// Get a user's shares as of the block before `timestamp`
+    pub fn get_past_shares(&self, user: Address, timestamp: u64) -> U512 {
+        let count = self.share_checkpoint_count.get(&user).unwrap_or(0);
+        self.checkpoint_before(count, timestamp, |i| self.share_checkpoints.get(&(user, i)))
+    }
+
+    /// Get the shares able to vote (total minus queued) as of the block before `timestamp`
+    pub fn get_past_total_shares(&self, timestamp: u64) -> U512 {
+        let count = self.supply_checkpoint_count.get().unwrap_or(0);
+        self.checkpoint_before(count, timestamp, |i| self.supply_checkpoints.get(&i))
+    }
+
+    // ========== Internal Functions ==========
+
+    fn require_governance_proposal(&self, proposal_id: u32) -> GovernanceProposal {
+        match self.get_governance_proposal(proposal_id) {
+            Some(p) => p,
+            None => self.env().revert(Error::ProposalNotFound),
+        }
+    }
+
+    /// Record the user's shares and the voting supply at the current block time
+    fn checkpoint_shares(&mut self, user: Address) {
+        let time = self.env().get_block_time();
+
+        let shares = self.get_user_position(&user).shares;
+        let count = self.share_checkpoint_count.get(&user).unwrap_or(0);
+        let last = count
+            .checked_sub(1)
+            .and_then(|i| self.share_checkpoints.get(&(user, i)));
+        let index = match last {
+            Some(l) if l.time == time => count - 1,
+            _ => {
+                self.share_checkpoint_count.set(&user, count + 1);
+                count
+            }
+        };
+        self.share_checkpoints
+            .set(&(user, index), ShareCheckpoint { time, shares });
+
+        let supply = self.get_total_shares() - self.queued_shares.get().unwrap_or(U512::zero());
+        let count = self.supply_checkpoint_count.get().unwrap_or(0);
+        let last = count
+            .checked_sub(1)
+            .and_then(|i| self.supply_checkpoints.get(&i));
+        let index = match last {
+            Some(l) if l.time == time => count - 1,
+            _ => {
+                self.supply_checkpoint_count.set(count + 1);
+                count
+            }
+        };
+        self.supply_checkpoints.set(
+            &index,
+            ShareCheckpoint {
+                time,
+                shares: supply,
+            },
+        );
+    }
+
+    /// Binary search for the latest checkpoint strictly before the timestamp
+    fn checkpoint_before(
+        &self,
+        count: u32,
+        timestamp: u64,
+        get: impl Fn(u32) -> Option<ShareCheckpoint>,
+    ) -> U512 {
+        let (mut low, mut high) = (0u32, count);
+        while low < high {
+            let mid = (low + high) / 2;
+            match get(mid) {
+                Some(c) if c.time < timestamp => low = mid + 1,
+                _ => high = mid,
+            }
+        }
+        match low.checked_sub(1).and_then(get) {
+            Some(c) => c.shares,
+            None => U512::zero(),
+        }
+    }
+
+    fn lock_boost(&self, duration: u64) -> u32 {
+        let max_duration = self.get_max_lock_duration();
+        if duration == 0 || duration > max_duration {
+            self.env().revert(Error::InvalidLockDuration)
+        }
+        (MAX_LOCK_BOOST as u64 * duration / max_duration) as u32
+    }
+
+    /// Replace a user's lock, keeping the total boost and expiry schedule in sync
+    /// (rewards must be settled first, so expiries up to now are already applied)
+    fn set_lock(&mut self, user: Address, lock: Option<ShareLock>) {
+        let now = self.env().get_block_time();
+        let mut total = self.total_lock_boost.get().unwrap_or(U512::zero());
+        if let Some(old) = self.get_lock(user) {
+            if old.unlock_at > now {
+                total -= lock_extra(&old);
+                self.schedule_lock_expiry(old.unlock_at, lock_extra(&old), false);
+            }
+        }
+        if let Some(new) = lock.as_ref() {
+            total += lock_extra(new);
+            self.schedule_lock_expiry(new.unlock_at, lock_extra(new), true);
+        }
+        self.total_lock_boost.set(total);
+        self.share_locks.set(&user, lock);
+    }
+
+    /// Add or remove extra weight leaving the total at `time`
+    fn schedule_lock_expiry(&mut self, time: u64, extra: U512, add: bool) {
+        let current = self.lock_expiries.get(&time).unwrap_or_default();
+        let updated = if add {
+            current + extra
+        } else {
+            current - extra
+        };
+        self.lock_expiries.set(&time, updated);
+
+        let mut times = self.lock_expiry_times.get().unwrap_or_default();
+        match times.binary_search(&time) {
+            Ok(i) if updated.is_zero() => {
+                times.remove(i);
+            }
+            Err(i) if !updated.is_zero() => times.insert(i, time),
+            _ => return,
+        }
+        self.lock_expiry_times.set(times);
+    }
+
+    /// Settle rewards and remove the lock (settling already removes an expired one)
+    fn release_lock(&mut self, user: Address) {
+        self.update_reward(Some(user));
+        if self.get_lock(user).is_some() {
+            self.drop_lock(user);
+        }
+    }
+
+    fn drop_lock(&mut self, user: Address) {
+        let shares = self.get_lock(user).map(|l| l.shares).unwrap_or_default();
+        self.set_lock(user, None);
+
+        self.env().emit_event(LockReleased {
+            user,
+            shares,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Revert unless `shares` can leave the user's position, releasing an expired lock
+    fn require_unlocked(&mut self, user: Address, shares: U512) {
+        let lock = match self.get_lock(user) {
+            Some(l) => l,
+            None => return,
+        };
+        if self.env().get_block_time() >= lock.unlock_at
+            || self.get_pause_state().emergency_mode
+            || self.is_exited()
+        {
+            self.release_lock(user);
+            return;
+        }
+        let free = self
+            .get_user_position(&user)
+            .shares
+            .saturating_sub(lock.shares);
+        if shares > free {
+            self.env().revert(Error::SharesLocked)
+        }
+    }
+
+    /// Bring the reward accumulator up to date and credit a user before their shares change
+    fn update_reward(&mut self, user: Option<Address>) {
+        let now = self.env().get_block_time();
+
+        // Expired boosts leave the total at their expiry, not when their owner is settled
+        let mut times = self.lock_expiry_times.get().unwrap_or_default();
+        let due = times.iter().take_while(|time| **time <= now).count();
+        if due > 0 {
+            for time in times.drain(..due) {
+                self.advance_reward(time);
+                let extra = self.lock_expiries.get(&time).unwrap_or_default();
+                let total = self.total_lock_boost.get().unwrap_or(U512::zero());
+                self.total_lock_boost.set(total - extra);
+                self.lock_expiries.set(&time, U512::zero());
+            }
+            self.lock_expiry_times.set(times);
+        }
+        self.advance_reward(now);
+        let reward_per_share = self.reward_per_share_stored.get().unwrap_or(U512::zero());
+
+        if let Some(user) = user {
+            for (token, amount) in self.unsettled_rewards(user, reward_per_share) {
+                let credited = self
+                    .user_reward_balance
+                    .get(&(user, token))
+                    .unwrap_or(U512::zero());
+                self.user_reward_balance
+                    .set(&(user, token), credited + amount);
+            }
+            self.user_reward_per_share_paid.set(&user, reward_per_share);
+
+            // The boost already left the total at expiry, drop the stale lock
+            if matches!(self.get_lock(user), Some(lock) if now >= lock.unlock_at) {
+                self.drop_lock(user);
+            }
+        }
+    }
+
+    /// Stream rewards up to `time` over the current stake, closing the interval
+    fn advance_reward(&mut self, time: u64) {
+        let last_update = self.reward_last_update.get().unwrap_or(0);
+        let period_finish = self.get_reward_period_finish();
+        let until = time.min(period_finish);
+        if until <= last_update {
+            return;
+        }
+
+        let rate = self.get_reward_rate();
+        let staked = self.reward_staked();
+        let stored = self.reward_per_share_stored.get().unwrap_or(U512::zero());
+
+        // Whatever streamed over a non-empty stake is owed in the current token
+        if let Some(token) = self.reward_token.get() {
+            if staked > U512::zero() {
+                let owed = self.reward_owed.get(&token).unwrap_or_default();
+                self.reward_owed
+                    .set(&token, owed + rate * U512::from(until - last_update));
+            }
+        }
+
+        // Close the interval with the stake it was streamed over
+        let index = self.reward_sample_count.get().unwrap_or(0);
+        self.reward_samples.set(
+            &index,
+            RewardSample {
+                time: last_update,
+                reward_per_share: stored,
+                rate,
+                period_finish,
+                staked,
+            },
+        );
+        self.reward_sample_count.set(index + 1);
+
+        self.reward_per_share_stored
+            .set(stored + reward_increment(rate, until - last_update, staked));
+        self.reward_last_update.set(until);
+    }
+
+    /// Shares earning rewards: the supply outside the withdrawal queue plus lock boosts
+    fn reward_staked(&self) -> U512 {
+        self.get_total_shares() - self.queued_shares.get().unwrap_or(U512::zero())
+            + self.total_lock_boost.get().unwrap_or(U512::zero())
+    }
+
+    /// Reward per share at `time`, which must not be before the last reward update.
+    /// Lock expiries not applied yet shrink the stake at their expiry time.
+    fn reward_per_share_until(&self, time: u64) -> U512 {
+        let rate = self.get_reward_rate();
+        let end = time
+            .min(self.env().get_block_time())
+            .min(self.get_reward_period_finish());
+        let mut reward_per_share = self.reward_per_share_stored.get().unwrap_or(U512::zero());
+        let mut from = self.reward_last_update.get().unwrap_or(0);
+        let mut staked = self.reward_staked();
+
+        for expiry in self.lock_expiry_times.get().unwrap_or_default() {
+            if expiry >= end {
+                break;
+            }
+            if expiry > from {
+                reward_per_share += reward_increment(rate, expiry - from, staked);
+                from = expiry;
+            }
+            staked -= self.lock_expiries.get(&expiry).unwrap_or_default();
+        }
+        if end > from {
+            reward_per_share += reward_increment(rate, end - from, staked);
+        }
+        reward_per_share
+    }
+
+    /// Reward per share at any past time, from the sample covering it
+    fn reward_per_share_at(&self, time: u64) -> U512 {
+        if time >= self.reward_last_update.get().unwrap_or(0) {
+            return self.reward_per_share_until(time);
+        }
+
+        let count = self.reward_sample_count.get().unwrap_or(0);
+        let (mut low, mut high) = (0u64, count);
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.reward_samples.get(&mid) {
+                Some(s) if s.time <= time => low = mid + 1,
+                _ => high = mid,
+            }
+        }
+        match low.checked_sub(1).and_then(|i| self.reward_samples.get(&i)) {
+            Some(s) => {
+                let elapsed = time.min(s.period_finish).saturating_sub(s.time);
+                s.reward_per_share + reward_increment(s.rate, elapsed, s.staked)
+            }
+            None => U512::zero(),
+        }
+    }
+
+    /// Rewards earned since the user's last update, split by the token that was streaming
+    fn unsettled_rewards(&self, user: Address, reward_per_share: U512) -> Vec<(Address, U512)> {
+        let shares = self.get_user_position(&user).shares;
+        let paid = self
+            .user_reward_per_share_paid
+            .get(&user)
+            .unwrap_or(U512::zero());
+
+        // The boost only earns until the lock expires
+        let (extra, boost_end) = match self.get_lock(user) {
+            Some(lock) => (
+                lock_extra(&lock),
+                self.reward_per_share_at(lock.unlock_at)
+                    .min(reward_per_share),
+            ),
+            None => (U512::zero(), U512::zero()),
+        };
+
+        let epochs = self.get_reward_token_epochs();
+        let mut rewards = Vec::new();
+        for (i, epoch) in epochs.iter().enumerate() {
+            let start = epoch.start_reward_per_share.max(paid);
+            let end = epochs
+                .get(i + 1)
+                .map(|next| next.start_reward_per_share)
+                .unwrap_or(reward_per_share);
+            if end > start {
+                let mut earned = shares * (end - start);
+                let boosted_end = end.min(boost_end);
+                if boosted_end > start {
+                    earned += extra * (boosted_end - start);
+                }
+                rewards.push((epoch.token, earned / U512::from(REWARD_PRECISION)));
+            }
+        }
+        rewards
+    }
+
+    /// Charge the management fee accrued on a position in shares.
+    /// A referrer's cut of a referred user's fee is credited to their position,
+    /// the rest is burned so its value stays with the vault.
+    fn accrue_management_fee(&mut self, user: Address) {
+        let now = self.env().get_block_time();
+        let elapsed = now - self.fee_accrued_at.get(&user).unwrap_or(now);
+        self.fee_accrued_at.set(&user, now);
+
+        let shares = self.get_user_position(&user).shares;
+        let fee_shares = (shares * U512::from(self.get_management_fee()) * U512::from(elapsed)
+            / (U512::from(10000u32) * U512::from(YEAR_MS)))
+        .min(shares);
+        if fee_shares.is_zero() {
+            return;
+        }
+
+        let referrer = self.get_referrer(user);
+        let referrer_shares = match referrer {
+            Some(_) => fee_shares * U512::from(self.get_referral_share()) / U512::from(10000u32),
+            None => U512::zero(),
+        };
+
+        // The referrer pays their own fee before their balance grows
+        if let Some(referrer) = referrer {
+            self.accrue_management_fee(referrer);
+            self.update_reward(Some(referrer));
+        }
+        self.update_reward(Some(user));
+
+        let earned = self.calculate_withdrawal_amount(referrer_shares);
+
+        let mut position = self.get_user_position(&user);
+        position.shares -= fee_shares;
+        let remaining = position.shares;
+        self.user_positions.set(&user, position);
+
+        let total_shares = self.get_total_shares();
+        self.total_shares
+            .set(total_shares - fee_shares + referrer_shares);
+
+        // A lock can't cover more shares than the position still holds
+        if let Some(mut lock) = self.get_lock(user) {
+            if lock.shares > remaining {
+                lock.shares = remaining;
+                self.set_lock(user, Some(lock));
+            }
+        }
+        self.checkpoint_shares(user);
+
+        if let Some(referrer) = referrer {
+            let mut referrer_position = self.get_user_position(&referrer);
+            referrer_position.shares += referrer_shares;
+            self.user_positions.set(&referrer, referrer_position);
+            self.checkpoint_shares(referrer);
+
+            let mut stats = self.get_referral_stats(referrer);
+            stats.earnings += earned;
+            self.referral_stats.set(&referrer, stats);
+        }
+
+        self.env().emit_event(ManagementFeeCharged {
+            user,
+            referrer,
+            fee_shares,
+            referrer_shares,
+            timestamp: now,
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    fn deposit_internal(&mut self, caller: Address, owner: Address, amount: U512) {
+        self.require_not_paused(Operation::Deposit);
+        self.require_positive_amount(amount);
+
+        self.require_not_blocked(caller);
+        self.require_not_blocked(owner);
+        self.require_allowlisted(owner);
+        self.accrue_management_fee(owner);
+        self.update_reward(Some(owner));
+
+        if let Some(referrer) = self.get_referrer(owner) {
+            let mut stats = self.get_referral_stats(referrer);
+            stats.referred_deposits += amount;
+            self.referral_stats.set(&referrer, stats);
+        }
+
+        let mut position = self.get_user_position(&owner);
+        self.require_within_limits(&position, amount);
+
+        let shares = self.calculate_shares(amount);
+        self.dilute_cooldown(owner, position.shares, shares);
+
+        // Update user position
+        position.shares += shares;
+        position.deposited_amount += amount;
+        position.last_deposit_time = self.env().get_block_time();
+
+        let user_shares = position.shares;
+        self.user_positions.set(&owner, position);
+
+        // Update global state
+        let current_tvl = self.total_tvl.get().unwrap_or(U512::zero());
+        self.total_tvl.set(current_tvl + amount);
+
+        let current_shares = self.total_shares.get().unwrap_or(U512::zero());
+        self.total_shares.set(current_shares + shares);
+        self.checkpoint_shares(owner);
+
+        // Emit event
+        self.env().emit_event(Deposit {
+            user: owner,
+            caller,
+            owner,
+            receiver: owner,
+            amount,
+            shares,
+            user_shares,
+            total_tvl: self.get_tvl(),
+            total_shares: self.get_total_shares(),
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    fn set_operator_internal(&mut self, owner: Address, operator: Address, approved: bool) {
+        self.operators.set(&(owner, operator), approved);
+
+        self.env().emit_event(OperatorSet {
+            owner,
+            operator,
+            approved,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    fn withdraw_internal(
+        &mut self,
+        caller: Address,
+        owner: Address,
+        shares_to_withdraw: U512,
+        receiver: Address,
+    ) {
+        self.require_not_paused(Operation::Withdrawal);
+        self.require_positive_amount(shares_to_withdraw);
+
+        self.require_not_blocked(caller);
+        self.require_not_blocked(owner);
+        self.require_not_blocked(receiver);
+        self.accrue_management_fee(owner);
+        self.update_reward(Some(owner));
+        let mut position = self.get_user_position(&owner);
+
+        // Verify user has enough shares
+        if position.shares < shares_to_withdraw {
+            self.env().revert(Error::InsufficientShares)
+        }
+        self.consume_cooldown(owner, shares_to_withdraw);
+        self.require_unlocked(owner, shares_to_withdraw);
+
+        // Calculate withdrawal amount (shares to tokens)
+        let amount = self.calculate_withdrawal_amount(shares_to_withdraw);
+
+        // Instant withdrawals are paid from idle funds only, see `request_withdrawal`
+        if self.get_idle_liquidity() < amount {
+            self.env().revert(Error::InsufficientBalance)
+        }
+        let current_tvl = self.total_tvl.get().unwrap_or(U512::zero());
+
+        // Update user position
+        position.shares -= shares_to_withdraw;
+        if position.shares == U512::zero() {
+            // Reset position if fully withdrawn
+            position.deposited_amount = U512::zero();
+            position.total_rewards = U512::zero();
+        }
+        let user_shares = position.shares;
+        self.user_positions.set(&owner, position);
+
+        // Update global state
+        self.total_tvl.set(current_tvl - amount);
+
+        let current_shares = self.total_shares.get().unwrap_or(U512::zero());
+        self.total_shares.set(current_shares - shares_to_withdraw);
+        self.checkpoint_shares(owner);
+
+        // Emit event
+        self.env().emit_event(Withdrawal {
+            user: owner,
+            caller,
+            owner,
+            receiver,
+            amount,
+            shares: shares_to_withdraw,
+            user_shares,
+            total_tvl: self.get_tvl(),
+            total_shares: self.get_total_shares(),
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    fn calculate_shares(&self, amount: U512) -> U512 {
+        let total_shares = self.total_shares.get().unwrap_or(U512::zero());
+        let total_tvl = self.total_tvl.get().unwrap_or(U512::zero());
+
+        if total_shares == U512::zero() || total_tvl == U512::zero() {
+            // First deposit: shares = amount (1:1 ratio)
+            return amount;
+        }
+
+        // shares = (amount * total_shares) / total_tvl
+        amount * total_shares / total_tvl
+    }
+
+    fn calculate_withdrawal_amount(&self, shares: U512) -> U512 {
+        let total_shares = self.total_shares.get().unwrap_or(U512::zero());
+        let total_tvl = self.total_tvl.get().unwrap_or(U512::zero());
+
+        if total_shares == U512::zero() {
+            return U512::zero();
+        }
+
+        // amount = (shares * total_tvl) / total_shares
+        shares * total_tvl / total_shares
+    }
+
+    fn get_user_position(&self, user: &Address) -> UserPosition {
+        self.user_positions.get(user).unwrap_or_default()
+    }
+
+    fn execute_proposal(&mut self, proposal: Proposal) {
+        self.call_self(&proposal.entry_point, &proposal.args);
 
-        // Emit event
-        self.env().emit_event(Deposit {
-            user: caller,
-            amount,
-            shares,
+        self.env().emit_event(ProposalExecuted {
+            proposal_id: proposal.id,
+            entry_point: proposal.entry_point,
             timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
         });
     }
 
-    /// Withdraw funds from the yield optimizer
-    pub fn withdraw(&mut self, shares_to_withdraw: U512) {
-        self.require_not_paused();
-        self.require_positive_amount(shares_to_withdraw);
+    /// The contract calls itself, so privileged checks see the proposal as caller
+    fn call_self(&mut self, entry_point: &str, args: &Bytes) {
+        let args = match RuntimeArgs::from_bytes(args) {
+            Ok((args, _)) => args,
+            Err(_) => self.env().revert(Error::InvalidProposalArgs),
+        };
+        let call = CallDef::new(entry_point.to_string(), true, args);
+        self.env()
+            .call_contract::<()>(self.env().self_address(), call);
+    }
 
-        let caller = self.env().caller();
-        let mut position = self.get_user_position(&caller);
+    /// Calls that could keep the vault paused past its maximum duration need every signer
+    fn required_approvals(&self, entry_point: &str) -> u32 {
+        let unanimous = match entry_point {
+            "extend_pause" => true,
+            "pause" | "set_pause_flags" => self.pause_recently_ended(),
+            "set_max_pause_duration" => self.pause_in_force() || self.pause_recently_ended(),
+            _ => false,
+        };
+        if unanimous {
+            self.get_signers().len() as u32
+        } else {
+            self.get_threshold()
+        }
+    }
 
-        // Verify user has enough shares
-        if position.shares < shares_to_withdraw {
-            self.env().revert(Error::InsufficientShares)
+    /// Privileged calls come from executed proposals; a committee with
+    /// threshold 1 lets its signers call them directly.
+    fn require_owner(&self) {
+        let caller = self.env().caller();
+        if caller == self.env().self_address() {
+            return;
         }
 
-        // Calculate withdrawal amount (shares to tokens)
-        let amount = self.calculate_withdrawal_amount(shares_to_withdraw);
+        if self.get_threshold() > 1 || !self.is_signer(caller) {
+            self.env().revert(Error::NotOwner)
+        }
+    }
 
-        // Verify contract has enough balance
-        let current_tvl = self.total_tvl.get().unwrap_or(U512::zero());
-        if current_tvl < amount {
-            self.env().revert(Error::InsufficientBalance)
+    fn require_committee(&self) {
+        if self.env().caller() != self.env().self_address() {
+            self.env().revert(Error::NotCommittee)
         }
+    }
 
-        // Update user position
-        position.shares = position.shares - shares_to_withdraw;
-        if position.shares == U512::zero() {
-            // Reset position if fully withdrawn
-            position.deposited_amount = U512::zero();
-            position.total_rewards = U512::zero();
+    fn require_signer(&self, address: Address) {
+        if !self.is_signer(address) {
+            self.env().revert(Error::NotSigner)
         }
-        self.user_positions.set(&caller, position);
+    }
 
-        // Update global state
-        self.total_tvl.set(current_tvl - amount);
+    fn update_pause_state(&mut self, mut state: PauseState, reason: String) {
+        let was_paused = self.is_paused().paused;
+        let flags_were_active = self.pause_in_force();
+        if !flags_were_active && state.any_flag_set() && self.pause_recently_ended() {
+            // Renewing a pause right after it ended would defeat the maximum duration
+            self.require_committee();
+        }
+        if flags_were_active && !state.any_flag_set() {
+            self.pause_ended_at.set(self.env().get_block_time());
+        }
 
-        let current_shares = self.total_shares.get().unwrap_or(U512::zero());
-        self.total_shares.set(current_shares - shares_to_withdraw);
+        if !state.any_paused() {
+            state.reason = String::new();
+            state.paused_at = 0;
+            state.extended_until = 0;
+        } else {
+            if !was_paused {
+                // A fresh pause starts a new expiry window
+                state.paused_at = self.env().get_block_time();
+                state.extended_until = 0;
+            }
+            if !reason.is_empty() {
+                state.reason = reason;
+            }
+        }
+        self.pause_state.set(state.clone());
 
-        // Emit event
-        self.env().emit_event(Withdrawal {
-            user: caller,
-            amount,
-            shares: shares_to_withdraw,
+        self.env().emit_event(PauseStateChanged {
+            deposits_paused: state.deposits_paused,
+            withdrawals_paused: state.withdrawals_paused,
+            allocations_paused: state.allocations_paused,
+            harvests_paused: state.harvests_paused,
+            emergency_mode: state.emergency_mode,
+            reason: state.reason.clone(),
             timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
         });
-    }
 
-    /// Get user position details
-    pub fn get_position(&self, user: Address) -> UserPosition {
-        self.get_user_position(&user)
+        let is_paused = self.is_paused().paused;
+        if is_paused && !was_paused {
+            self.env().emit_event(Paused {
+                reason: state.reason,
+                timestamp: self.env().get_block_time(),
+                nonce: self.next_event_nonce(),
+            });
+        } else if !is_paused && was_paused {
+            self.env().emit_event(Unpaused {
+                timestamp: self.env().get_block_time(),
+                nonce: self.next_event_nonce(),
+            });
+        }
     }
 
-    /// Get total value locked
-    pub fn get_tvl(&self) -> U512 {
-        self.total_tvl.get().unwrap_or(U512::zero())
+    fn pause_expiry(&self, state: &PauseState) -> Option<u64> {
+        let max_duration = self.get_max_pause_duration();
+        if max_duration == 0 || !state.any_flag_set() {
+            return None;
+        }
+
+        Some(core::cmp::max(
+            state.paused_at + max_duration,
+            state.extended_until,
+        ))
     }
 
-    /// Get total shares issued
-    pub fn get_total_shares(&self) -> U512 {
-        self.total_shares.get().unwrap_or(U512::zero())
+    /// Pause flags are set and have not expired
+    fn pause_in_force(&self) -> bool {
+        let state = self.get_pause_state();
+        state.any_flag_set() && !self.pause_expired(&state)
     }
 
-    /// Get user's current value (including rewards)
-    pub fn get_user_value(&self, user: Address) -> U512 {
-        let position = self.get_user_position(&user);
-        if position.shares == U512::zero() {
-            return U512::zero();
+    /// The last pause expired or was lifted less than the maximum duration ago
+    fn pause_recently_ended(&self) -> bool {
+        let max_duration = self.get_max_pause_duration();
+        if max_duration == 0 {
+            return false;
         }
 
-        self.calculate_withdrawal_amount(position.shares)
+        let state = self.get_pause_state();
+        let ended_at = if !state.any_flag_set() {
+            self.pause_ended_at.get()
+        } else if self.pause_expired(&state) {
+            self.pause_expiry(&state)
+        } else {
+            None
+        };
+        match ended_at {
+            Some(ended_at) => self.env().get_block_time() < ended_at + max_duration,
+            None => false,
+        }
     }
 
-    /// Add a new liquidity pool (owner only)
-    pub fn add_pool(&mut self, name: String, initial_apy: u32, risk_level: u8) {
-        self.require_owner();
+    /// Expiry lifts the pause flags only, emergency mode stays in force
+    fn pause_expired(&self, state: &PauseState) -> bool {
+        match self.pause_expiry(state) {
+            Some(expiry) => self.env().get_block_time() >= expiry,
+            None => false,
+        }
+    }
 
-        let pool = PoolInfo {
-            name: name.clone(),
-            total_allocated: U512::zero(),
-            current_apy: initial_apy,
-            risk_level,
+    /// Undelegate a delegation pool's stake once it leaves the strategy
+    fn unbond_pool(&mut self, pool_name: String) {
+        let mut pool = match self.pools.get(&pool_name) {
+            Some(p) => p,
+            None => return,
+        };
+        let validator = match pool.validator.clone() {
+            Some(v) => v,
+            None => return,
         };
 
-        self.pools.set(&name, pool);
+        let amount = pool
+            .total_allocated
+            .min(self.env().delegated_amount(validator.clone()));
+        if amount == U512::zero() {
+            return;
+        }
+        self.env().undelegate(validator.clone(), amount);
+        self.record_unbonding(validator, pool_name.clone(), amount);
+
+        pool.total_allocated -= amount;
+        self.pools.set(&pool_name, pool);
+        let total_allocated = self.get_total_allocated();
+        self.total_allocated.set(total_allocated - amount);
+
+        self.env().emit_event(PoolDeallocated {
+            pool: pool_name,
+            amount,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
     }
 
-    /// Update pool APY (owner only)
-    pub fn update_pool_apy(&mut self, pool_name: String, new_apy: u32) {
-        self.require_owner();
+    /// Book a delegation pool's auction rewards or slashing losses against TVL
+    /// and measure its APY over the time since the last sync
+    fn sync_delegation_internal(&mut self, mut pool: PoolInfo, validator: PublicKey) -> PoolInfo {
+        let delegated = self.env().delegated_amount(validator);
+        let rewards = delegated.saturating_sub(pool.total_allocated);
+        let loss = pool.total_allocated.saturating_sub(delegated);
 
-        if let Some(mut pool) = self.pools.get(&pool_name) {
-            pool.current_apy = new_apy;
-            self.pools.set(&pool_name, pool);
-        } else {
-            self.env().revert(Error::PoolNotFound)
+        // Annualize the rewards earned on the allocation since the last sync
+        let now = self.env().get_block_time();
+        let elapsed = now.saturating_sub(pool.apy_updated_at);
+        let old_apy = pool.current_apy;
+        let measured = elapsed > 0 && pool.total_allocated > U512::zero();
+        if measured {
+            let apy = rewards * U512::from(10000u32) * U512::from(YEAR_MS)
+                / (pool.total_allocated * U512::from(elapsed));
+            pool.current_apy = apy.min(U512::from(u32::MAX)).as_u32();
+        }
+
+        pool.total_allocated = delegated;
+        pool.apy_updated_at = now;
+        self.pools.set(&pool.name, pool.clone());
+        if measured {
+            self.record_apy_checkpoint(&pool.name, pool.current_apy);
+            self.env().emit_event(PoolApyUpdated {
+                pool: pool.name.clone(),
+                old_apy,
+                new_apy: pool.current_apy,
+                timestamp: now,
+                nonce: self.next_event_nonce(),
+            });
         }
+
+        let total_allocated = self.get_total_allocated();
+        self.total_allocated.set(total_allocated + rewards - loss);
+        let current_tvl = self.get_tvl();
+        self.total_tvl.set(current_tvl + rewards - loss);
+        self.record_pps_checkpoint();
+
+        self.env().emit_event(DelegationSynced {
+            pool: pool.name.clone(),
+            delegated,
+            rewards,
+            loss,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+        pool
     }
 
-    /// Get pool information
-    pub fn get_pool_info(&self, pool_name: String) -> Option<PoolInfo> {
-        self.pools.get(&pool_name)
+    /// Withdraws a pool's allocation back to idle, returning the amount recovered
+    fn recover_pool_funds(&mut self, pool: &PoolInfo) -> U512 {
+        match pool.validator.clone() {
+            // Undelegate the whole stake, motes return after the unbonding delay
+            Some(validator) => {
+                let delegated = self.env().delegated_amount(validator.clone());
+                if delegated > U512::zero() {
+                    self.env().undelegate(validator.clone(), delegated);
+                    self.record_unbonding(validator, pool.name.clone(), delegated);
+                }
+                delegated
+            }
+            // Bookkeeping pools hold no external position, the allocation is idle funds
+            None => pool.total_allocated,
+        }
     }
 
-    /// Allocate funds to a pool (owner only)
-    pub fn allocate_to_pool(&mut self, pool_name: String, amount: U512) {
-        self.require_owner();
-        self.require_positive_amount(amount);
+    fn require_not_paused(&self, operation: Operation) {
+        if self.is_exited() && matches!(operation, Operation::Deposit | Operation::Allocation) {
+            self.env().revert(Error::VaultExited)
+        }
 
-        if let Some(mut pool) = self.pools.get(&pool_name) {
-            // Verify sufficient TVL
-            let current_tvl = self.total_tvl.get().unwrap_or(U512::zero());
-            if current_tvl < amount {
-                self.env().revert(Error::InsufficientTvl)
+        if self.is_operation_paused(operation) {
+            self.env().revert(Error::ContractPaused)
+        }
+    }
+
+    fn is_operation_paused(&self, operation: Operation) -> bool {
+        if self.is_exited() {
+            match operation {
+                Operation::Deposit | Operation::Allocation => return true,
+                // Holders can always withdraw their share of an exited vault
+                Operation::Withdrawal => return false,
+                Operation::Harvest => {}
             }
+        }
 
-            pool.total_allocated = pool.total_allocated + amount;
-            self.pools.set(&pool_name, pool);
-        } else {
-            self.env().revert(Error::PoolNotFound)
+        let mut state = self.get_pause_state();
+        if self.pause_expired(&state) {
+            state = PauseState {
+                emergency_mode: state.emergency_mode,
+                ..PauseState::default()
+            };
+        }
+
+        match operation {
+            Operation::Deposit => state.deposits_paused || state.emergency_mode,
+            // Users can always withdraw their pro-rata share in emergency mode
+            Operation::Withdrawal => state.withdrawals_paused && !state.emergency_mode,
+            Operation::Allocation => state.allocations_paused || state.emergency_mode,
+            Operation::Harvest => state.harvests_paused,
         }
     }
 
-    /// Rebalance between pools (owner only)
-    pub fn rebalance_pools(&mut self, from_pool: String, to_pool: String, amount: U512) {
-        self.require_owner();
-        self.require_positive_amount(amount);
+    /// Moves shares and a proportional part of the deposited amount and rewards
+    fn move_shares(&mut self, from: Address, to: Address, shares: U512) {
+        self.accrue_management_fee(from);
+        self.accrue_management_fee(to);
+        self.update_reward(Some(from));
+        self.update_reward(Some(to));
+        let mut from_position = self.get_user_position(&from);
+        if from_position.shares < shares {
+            self.env().revert(Error::InsufficientShares)
+        }
 
-        // Get source pool
-        let mut from = match self.pools.get(&from_pool) {
-            Some(p) => p,
-            None => self.env().revert(Error::PoolNotFound),
-        };
+        let deposited = from_position.deposited_amount * shares / from_position.shares;
+        let rewards = from_position.total_rewards * shares / from_position.shares;
 
-        // Get destination pool
-        let mut to = match self.pools.get(&to_pool) {
-            Some(p) => p,
-            None => self.env().revert(Error::PoolNotFound),
+        from_position.shares -= shares;
+        from_position.deposited_amount -= deposited;
+        from_position.total_rewards -= rewards;
+        self.user_positions.set(&from, from_position);
+
+        let mut to_position = self.get_user_position(&to);
+        self.dilute_cooldown(to, to_position.shares, shares);
+        to_position.shares += shares;
+        to_position.deposited_amount += deposited;
+        to_position.total_rewards += rewards;
+        self.user_positions.set(&to, to_position);
+        self.checkpoint_shares(from);
+        self.checkpoint_shares(to);
+
+        self.env().emit_event(SharesTransferred {
+            from,
+            to,
+            shares,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
+    }
+
+    /// Spend cooled-down shares, skipped when cooldown is off or the vault is winding down
+    fn consume_cooldown(&mut self, user: Address, shares: U512) {
+        if self.cooldown_period.get().unwrap_or(0) == 0
+            || self.get_pause_state().emergency_mode
+            || self.is_exited()
+        {
+            return;
+        }
+
+        let mut cooldown = match self.get_cooldown(user) {
+            Some(c) => c,
+            None => self.env().revert(Error::CooldownNotStarted),
         };
+        let now = self.env().get_block_time();
+        if now < cooldown.withdrawable_from {
+            self.env().revert(Error::CooldownNotElapsed)
+        }
+        if now > cooldown.withdrawable_until {
+            self.env().revert(Error::CooldownWindowClosed)
+        }
+        if cooldown.shares < shares {
+            self.env().revert(Error::CooldownSharesExceeded)
+        }
 
-        // Verify sufficient allocation in source pool
-        if from.total_allocated < amount {
-            self.env().revert(Error::InsufficientAllocation)
+        cooldown.shares -= shares;
+        if cooldown.shares == U512::zero() {
+            self.cooldowns.set(&user, None);
+        } else {
+            self.cooldowns.set(&user, Some(cooldown));
         }
+    }
 
-        // Update allocations
-        from.total_allocated = from.total_allocated - amount;
-        to.total_allocated = to.total_allocated + amount;
+    /// Push a running cooldown back by the time-weighted share of incoming shares,
+    /// so fresh shares can't ride on an elapsed cooldown
+    fn dilute_cooldown(&mut self, user: Address, balance: U512, incoming: U512) {
+        let mut cooldown = match self.get_cooldown(user) {
+            Some(c) => c,
+            None => return,
+        };
+        let now = self.env().get_block_time();
+        if now > cooldown.withdrawable_until || incoming == U512::zero() {
+            return;
+        }
 
-        self.pools.set(&from_pool, from);
-        self.pools.set(&to_pool, to);
+        let started_at = (U512::from(cooldown.started_at) * balance + U512::from(now) * incoming)
+            / (balance + incoming);
+        let shift = started_at.as_u64() - cooldown.started_at;
+        cooldown.started_at += shift;
+        cooldown.withdrawable_from += shift;
+        cooldown.withdrawable_until += shift;
+        self.cooldowns.set(&user, Some(cooldown));
+    }
 
-        // Emit event
-        self.env().emit_event(Rebalance {
-            from_pool,
-            to_pool,
-            amount,
-            timestamp: self.env().get_block_time(),
-        });
+    fn total_target_weight(&self) -> u32 {
+        self.get_strategy_pools()
+            .iter()
+            .filter_map(|name| self.get_validator_target(name.clone()))
+            .map(|target| target.weight)
+            .sum()
     }
 
-    /// Harvest rewards from a pool (owner only)
-    pub fn harvest_rewards(&mut self, pool_name: String, amount: U512) {
-        self.require_owner();
-        self.require_positive_amount(amount);
+    fn record_unbonding(&mut self, validator: PublicKey, pool: String, amount: U512) {
+        let now = self.env().get_block_time();
+        let available_at = now + self.get_unbonding_period();
 
-        // Verify pool exists
-        if self.pools.get(&pool_name).is_none() {
-            self.env().revert(Error::PoolNotFound)
+        let mut entries = self.get_unbonding(validator.clone());
+        if entries.is_empty() {
+            let mut validators = self.unbonding_validators.get().unwrap_or_default();
+            validators.push(validator.clone());
+            self.unbonding_validators.set(validators);
         }
+        entries.push(UnbondingEntry {
+            pool: pool.clone(),
+            amount,
+            available_at,
+        });
+        self.unbonding.set(&validator, entries);
 
-        // Add rewards to TVL
-        let current_tvl = self.total_tvl.get().unwrap_or(U512::zero());
-        self.total_tvl.set(current_tvl + amount);
+        let total_unbonding = self.get_total_unbonding();
+        self.total_unbonding.set(total_unbonding + amount);
 
-        // Emit event
-        self.env().emit_event(RewardsHarvested {
-            pool: pool_name,
+        self.env().emit_event(UnbondingStarted {
+            pool,
             amount,
-            timestamp: self.env().get_block_time(),
+            available_at,
+            timestamp: now,
+            nonce: self.next_event_nonce(),
         });
     }
 
-    /// Get contract owner
-    pub fn get_owner(&self) -> Option<Address> {
-        self.owner.get()
-    }
+    /// Write a price-per-share checkpoint unless the last one is too recent
+    fn record_pps_checkpoint(&mut self) {
+        let now = self.env().get_block_time();
+        let count = self.pps_checkpoint_count.get().unwrap_or(0);
+        if count > 0 {
+            let last = self.pps_checkpoints.get(&((count - 1) % PPS_HISTORY_SIZE));
+            if let Some(last) = last {
+                if now < last.timestamp + self.get_pps_checkpoint_interval() {
+                    return;
+                }
+            }
+        }
 
-    /// Get management fee
-    pub fn get_management_fee(&self) -> u32 {
-        self.management_fee.get().unwrap_or(0)
+        self.pps_checkpoints.set(
+            &(count % PPS_HISTORY_SIZE),
+            PpsCheckpoint {
+                timestamp: now,
+                price_per_share: self.get_price_per_share(),
+            },
+        );
+        self.pps_checkpoint_count.set(count + 1);
     }
 
-    /// Emergency pause (owner only)
-    pub fn pause(&mut self) {
-        self.require_owner();
-        self.paused.set(true);
+    fn record_apy_checkpoint(&mut self, pool_name: &str, apy: u32) {
+        let pool_name = pool_name.to_string();
+        let count = self.pool_apy_history_count.get(&pool_name).unwrap_or(0);
+        self.pool_apy_history.set(
+            &(pool_name.clone(), count % APY_HISTORY_SIZE),
+            ApyCheckpoint {
+                timestamp: self.env().get_block_time(),
+                apy,
+            },
+        );
+        self.pool_apy_history_count.set(&pool_name, count + 1);
     }
 
-    /// Unpause contract (owner only)
-    pub fn unpause(&mut self) {
-        self.require_owner();
-        self.paused.set(false);
-    }
+    /// Verify a permit signed by owner_key and consume its nonce, returning the owner
+    fn use_permit(
+        &mut self,
+        owner_key: PublicKey,
+        action: &str,
+        target: Address,
+        amount: U512,
+        deadline: u64,
+        signature: Bytes,
+    ) -> Address {
+        if self.env().get_block_time() > deadline {
+            self.env().revert(Error::PermitExpired)
+        }
 
-    /// Check if contract is paused
-    pub fn is_paused(&self) -> bool {
-        self.paused.get().unwrap_or(false)
-    }
+        let owner = Address::from(owner_key.clone());
+        let permit_nonce = self.get_permit_nonce(owner);
+        let message = PermitMessage {
+            action: action.to_string(),
+            owner,
+            target,
+            amount,
+            nonce: permit_nonce,
+            deadline,
+            contract: self.env().self_address(),
+        };
+        let message = match message.to_bytes() {
+            Ok(bytes) => Bytes::from(bytes),
+            Err(_) => self.env().revert(Error::InvalidSignature),
+        };
+        if !self
+            .env()
+            .verify_signature(&message, &signature, &owner_key)
+        {
+            self.env().revert(Error::InvalidSignature)
+        }
+        self.permit_nonces.set(&owner, permit_nonce + 1);
 
-    // ========== Internal Functions ==========
+        self.env().emit_event(PermitUsed {
+            owner,
+            action: action.to_string(),
+            permit_nonce,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
 
-    fn calculate_shares(&self, amount: U512) -> U512 {
-        let total_shares = self.total_shares.get().unwrap_or(U512::zero());
-        let total_tvl = self.total_tvl.get().unwrap_or(U512::zero());
+        owner
+    }
 
-        if total_shares == U512::zero() || total_tvl == U512::zero() {
-            // First deposit: shares = amount (1:1 ratio)
-            return amount;
-        }
+    /// Reserve the sequence number of an event about to be emitted
+    fn next_event_nonce(&mut self) -> u64 {
+        let nonce = self.get_event_nonce();
+        self.event_nonce.set(nonce + 1);
+        nonce
+    }
 
-        // shares = (amount * total_shares) / total_tvl
-        amount * total_shares / total_tvl
+    fn is_pool_stale(&self, pool_name: &String) -> bool {
+        let max_age = self.max_apy_age.get().unwrap_or(0);
+        match self.pools.get(pool_name) {
+            Some(pool) if max_age > 0 => {
+                self.env().get_block_time() > pool.apy_updated_at + max_age
+            }
+            _ => false,
+        }
     }
 
-    fn calculate_withdrawal_amount(&self, shares: U512) -> U512 {
-        let total_shares = self.total_shares.get().unwrap_or(U512::zero());
-        let total_tvl = self.total_tvl.get().unwrap_or(U512::zero());
+    fn require_keeper(&self) {
+        if !self.is_keeper(self.env().caller()) {
+            self.env().revert(Error::NotKeeper)
+        }
+    }
 
-        if total_shares == U512::zero() {
-            return U512::zero();
+    fn require_guardian(&self) -> Address {
+        let caller = self.env().caller();
+        if !self.is_guardian(caller) {
+            self.env().revert(Error::NotGuardian)
         }
+        caller
+    }
 
-        // amount = (shares * total_tvl) / total_shares
-        shares * total_tvl / total_shares
+    fn require_not_blocked(&self, account: Address) {
+        if self.is_blocked(account) {
+            self.env().revert(Error::AddressBlocked)
+        }
     }
 
-    fn get_user_position(&self, user: &Address) -> UserPosition {
-        self.user_positions
-            .get(user)
-            .unwrap_or_else(|| UserPosition::default())
+    fn set_allowlisted(&mut self, account: Address, allowed: bool) {
+        self.allowlist.set(&account, allowed);
+
+        self.env().emit_event(AllowlistUpdated {
+            account,
+            allowed,
+            timestamp: self.env().get_block_time(),
+            nonce: self.next_event_nonce(),
+        });
     }
 
-    fn require_owner(&self) {
-        let owner = match self.owner.get() {
-            Some(addr) => addr,
-            None => self.env().revert(Error::NotOwner),
-        };
+    fn can_deposit(&self, account: Address) -> bool {
+        !self.is_allowlist_enabled() || self.is_allowlisted(account)
+    }
 
-        if self.env().caller() != owner {
-            self.env().revert(Error::NotOwner)
+    fn require_allowlisted(&self, account: Address) {
+        if !self.can_deposit(account) {
+            self.env().revert(Error::NotAllowlisted)
         }
     }
 
-    fn require_not_paused(&self) {
-        if self.paused.get().unwrap_or(false) {
-            self.env().revert(Error::ContractPaused)
+    fn require_within_limits(&self, position: &UserPosition, amount: U512) {
+        let limits = self.get_limits();
+
+        if amount < limits.min_deposit {
+            self.env().revert(Error::DepositBelowMinimum)
+        }
+        if limits.max_tvl > U512::zero() && self.get_tvl() + amount > limits.max_tvl {
+            self.env().revert(Error::TvlCapExceeded)
+        }
+        if limits.max_deposit_per_user > U512::zero()
+            && position.deposited_amount + amount > limits.max_deposit_per_user
+        {
+            self.env().revert(Error::UserDepositCapExceeded)
         }
     }
 
@@ -402,3 +4619,31 @@ impl YieldOptimizer {
         }
     }
 }
+
+/// Reward per share streamed at `rate` over `elapsed` ms, nothing while no stake earns
+fn reward_increment(rate: U512, elapsed: u64, staked: U512) -> U512 {
+    if staked == U512::zero() {
+        return U512::zero();
+    }
+    rate * U512::from(elapsed) * U512::from(REWARD_PRECISION) / staked
+}
+
+fn lock_extra(lock: &ShareLock) -> U512 {
+    lock.shares * U512::from(lock.boost) / U512::from(10000u32)
+}
+
+/// None if the value doesn't fit in 256 bits
+fn to_u256(value: U512) -> Option<U256> {
+    let mut bytes = [0u8; 64];
+    value.to_little_endian(&mut bytes);
+    if bytes[32..].iter().any(|b| *b != 0) {
+        return None;
+    }
+    Some(U256::from_little_endian(&bytes[..32]))
+}
+
+fn to_u512(value: U256) -> U512 {
+    let mut bytes = [0u8; 32];
+    value.to_little_endian(&mut bytes);
+    U512::from_little_endian(&bytes)
+}